@@ -1,8 +1,16 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand};
 
-use crate::daemon::DaemonStartArgs;
+use crate::{
+    daemon::DaemonStartArgs,
+    duration::{parse_duration, parse_non_zero_duration},
+    env::parse_env_var,
+    limits::IoPriority,
+    schedule::IntervalAnchor,
+    size::parse_size,
+    task::{Backoff, CatchUpPolicy, OverlapPolicy},
+};
 
 #[derive(Parser)]
 #[clap(version, about, author)]
@@ -10,7 +18,12 @@ pub struct Cmd {
     #[clap(short, long, help = "Path to the data directory")]
     pub data_dir: Option<PathBuf>,
 
-    #[clap(short, long, global = true, help = "Display debug messages")]
+    #[clap(
+        short,
+        long,
+        global = true,
+        help = "Display debug messages and more details"
+    )]
     pub verbose: bool,
 
     #[clap(subcommand)]
@@ -26,11 +39,23 @@ pub enum Action {
     Check,
 
     #[clap(about = "Register a task (if not registered yet)")]
-    Register(RegisterArgs),
+    Register(Box<RegisterArgs>),
 
     #[clap(about = "Unregister a task")]
     Unregister(UnregisterArgs),
 
+    #[clap(about = "Stop running a task until it is resumed")]
+    Pause(PauseArgs),
+
+    #[clap(about = "Resume a paused task")]
+    Resume(ResumeArgs),
+
+    #[clap(about = "Manage the calendars of exclusion windows tasks don't run during")]
+    Calendar(CalendarArgs),
+
+    #[clap(about = "Show when a task or a repetition pattern will run next")]
+    Next(NextArgs),
+
     #[clap(about = "Run a task immediatly")]
     Run(RunArgs),
 
@@ -54,19 +79,279 @@ pub enum Action {
 }
 
 #[derive(Args)]
+#[clap(group(ArgGroup::new("schedule")))]
+#[clap(group(
+    ArgGroup::new("trigger")
+        .required(true)
+        .multiple(true)
+        .args(["at", "every", "once", "after", "on_failure", "watch", "on_startup"])
+))]
+#[clap(group(ArgGroup::new("command").required(true).args(["run", "argv"])))]
 pub struct RegisterArgs {
     #[clap(help = "Name of the task")]
     pub name: String,
 
     #[clap(short, long, help = "The command to run")]
-    pub run: String,
+    pub run: Option<String>,
 
-    #[clap(long, help = "Times to run at (pattern like 'D=10,20 h=*")]
-    pub at: String,
+    #[clap(
+        last = true,
+        requires = "exec",
+        value_name = "PROGRAM",
+        help = "Program to run directly followed by its arguments, as is (with --exec)"
+    )]
+    pub argv: Vec<String>,
 
-    #[clap(long, help = "The shell to use")]
+    #[clap(
+        long,
+        group = "schedule",
+        help = "Times to run at (pattern like 'D=10,20 h=*' or cron expression like '*/5 * * * *')"
+    )]
+    pub at: Option<String>,
+
+    #[clap(
+        long,
+        group = "schedule",
+        value_parser = parse_non_zero_duration,
+        help = "Run at a fixed interval after the previous run (e.g. '90m' or '1h30m')"
+    )]
+    pub every: Option<u64>,
+
+    #[clap(
+        long,
+        group = "schedule",
+        help = "Run a single time at the provided date (e.g. '2026-11-02 22:00'), then remove the task once it succeeded"
+    )]
+    pub once: Option<String>,
+
+    #[clap(
+        long,
+        requires = "once",
+        help = "Keep the one-shot task once it succeeded, marked as done"
+    )]
+    pub keep: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        requires = "every",
+        default_value = "end",
+        help = "Count the interval from the previous run's start or end"
+    )]
+    pub anchor: IntervalAnchor,
+
+    #[clap(
+        long,
+        conflicts_with = "every",
+        help = "IANA time zone the repetition pattern or one-shot date is expressed in (e.g. 'Europe/Paris')"
+    )]
+    pub timezone: Option<String>,
+
+    #[clap(
+        long,
+        help = "The shell to use, split into arguments like a shell would (default: '/bin/sh -c')"
+    )]
     pub using: Option<String>,
 
+    #[clap(
+        long,
+        conflicts_with = "using",
+        help = "Run the command directly instead of through a shell, after splitting it into arguments like a shell would (unless they are provided after '--')"
+    )]
+    pub exec: bool,
+
+    #[clap(
+        long,
+        help = "Directory to run the command in (default: the daemon's working directory)"
+    )]
+    pub cwd: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser = parse_env_var,
+        help = "Environment variable to set for the command, like 'KEY=VALUE' (can be repeated)"
+    )]
+    pub env: Vec<(String, String)>,
+
+    #[clap(
+        long,
+        help = "File to load environment variables from before each run, with one 'KEY=VALUE' per line"
+    )]
+    pub env_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Run the command as this user, which requires the daemon to run as root"
+    )]
+    pub user: Option<String>,
+
+    #[clap(
+        long,
+        help = "Run the command as this group (default: the user's primary group)"
+    )]
+    pub group: Option<String>,
+
+    #[clap(
+        long,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i8).range(-20..=19),
+        help = "Niceness of the command, from -20 (most favored) to 19 (least favored by the scheduler)"
+    )]
+    pub nice: Option<i8>,
+
+    #[clap(
+        long,
+        value_parser = IoPriority::parse,
+        help = "I/O scheduling class and priority of the command, like 'idle', 'best-effort:7' or 'realtime:0'"
+    )]
+    pub ionice: Option<IoPriority>,
+
+    #[clap(
+        long,
+        value_parser = parse_non_zero_duration,
+        help = "Kill the command once it used this much CPU time (e.g. '10m')"
+    )]
+    pub max_cpu_time: Option<u64>,
+
+    #[clap(
+        long,
+        value_parser = parse_size,
+        help = "Maximum size of the command's address space (e.g. '512M' or '2G')"
+    )]
+    pub max_memory: Option<u64>,
+
+    #[clap(long, help = "Maximum number of files the command can open")]
+    pub max_open_files: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Maximum number of processes the user the command runs as can have"
+    )]
+    pub max_processes: Option<u64>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "skip",
+        help = "What to do when the task must run while a previous run is still going"
+    )]
+    pub overlap: OverlapPolicy,
+
+    #[clap(
+        long = "slot",
+        help = "Named slot the task must hold while running, shared with other tasks (can be repeated)"
+    )]
+    pub slots: Vec<String>,
+
+    #[clap(
+        long,
+        value_parser = parse_non_zero_duration,
+        help = "Stop the task if it is still running after this duration (e.g. '30m' or '1h')"
+    )]
+    pub timeout: Option<u64>,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(2..),
+        help = "Retry the task when it fails, up to this number of attempts (including the first one)"
+    )]
+    pub max_attempts: Option<u32>,
+
+    #[clap(
+        long,
+        requires = "max_attempts",
+        value_parser = parse_non_zero_duration,
+        default_value = "1m",
+        help = "Delay before retrying a failed task"
+    )]
+    pub retry_delay: u64,
+
+    #[clap(
+        long,
+        value_enum,
+        requires = "max_attempts",
+        default_value = "fixed",
+        help = "Keep the same delay between all attempts, or double it after each one"
+    )]
+    pub retry_backoff: Backoff,
+
+    #[clap(
+        long,
+        requires = "max_attempts",
+        allow_negative_numbers = true,
+        help = "Only retry when the task exits with this code (can be repeated)"
+    )]
+    pub retry_on: Vec<i32>,
+
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with = "every",
+        default_value = "run-once",
+        help = "What to do with the runs missed while the daemon was stopped or the computer was asleep"
+    )]
+    pub catch_up: CatchUpPolicy,
+
+    #[clap(
+        long,
+        conflicts_with = "every",
+        value_parser = parse_non_zero_duration,
+        help = "Shift the task's runs by a random delay up to this duration, which is stable on each host (e.g. '10m')"
+    )]
+    pub jitter: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Run when this task succeeds, in addition to the schedule (can be repeated)"
+    )]
+    pub after: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Run when this task fails after its last attempt, in addition to the schedule (can be repeated)"
+    )]
+    pub on_failure: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Run when files are created or written in this file or directory (can be repeated)"
+    )]
+    pub watch: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        requires = "watch",
+        help = "Only consider files whose name matches this glob pattern (e.g. '*.csv', can be repeated)"
+    )]
+    pub watch_glob: Vec<String>,
+
+    #[clap(
+        long,
+        requires = "watch",
+        value_parser = parse_non_zero_duration,
+        default_value = "1s",
+        help = "Wait for watched files to stop changing for this duration before running"
+    )]
+    pub debounce: u64,
+
+    #[clap(long, help = "Run when the daemon starts")]
+    pub on_startup: bool,
+
+    #[clap(
+        long,
+        requires = "on_startup",
+        value_parser = parse_duration,
+        default_value = "0",
+        help = "Wait for this duration after the daemon started before running"
+    )]
+    pub startup_delay: u64,
+
+    #[clap(
+        long,
+        help = "Don't run during the exclusion windows of this calendar (can be repeated)"
+    )]
+    pub calendar: Vec<String>,
+
     #[clap(long, help = "Override any task with the provided name")]
     pub force_override: bool,
 
@@ -86,6 +371,111 @@ pub struct UnregisterArgs {
     pub name: String,
 }
 
+#[derive(Args)]
+pub struct PauseArgs {
+    #[clap(help = "Name of the task to pause")]
+    pub name: String,
+
+    #[clap(
+        long,
+        help = "Resume the task automatically at the provided date (e.g. '2026-11-02 08:00')"
+    )]
+    pub until: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ResumeArgs {
+    #[clap(help = "Name of the task to resume")]
+    pub name: String,
+}
+
+#[derive(Args)]
+pub struct CalendarArgs {
+    #[clap(subcommand)]
+    pub action: CalendarAction,
+}
+
+#[derive(Subcommand)]
+pub enum CalendarAction {
+    #[clap(about = "List the calendars and their exclusion windows")]
+    List,
+
+    #[clap(about = "Import a calendar from a file")]
+    Import(CalendarImportArgs),
+
+    #[clap(about = "Remove a calendar")]
+    Remove(CalendarRemoveArgs),
+}
+
+#[derive(Args)]
+pub struct CalendarImportArgs {
+    #[clap(help = "Name of the calendar")]
+    pub name: String,
+
+    #[clap(
+        help = "iCalendar file (.ics), or simple file with one exclusion window per line (e.g. '2026-12-25', '2026-12-24 .. 2026-12-26' or 'every W=sun h=2 for 2h')"
+    )]
+    pub file: PathBuf,
+
+    #[clap(
+        long,
+        help = "IANA time zone the dates without an explicit one are expressed in (e.g. 'Europe/Paris')"
+    )]
+    pub timezone: Option<String>,
+
+    #[clap(long, help = "Replace any calendar with the provided name")]
+    pub force_override: bool,
+}
+
+#[derive(Args)]
+pub struct CalendarRemoveArgs {
+    #[clap(help = "Name of the calendar to remove")]
+    pub name: String,
+}
+
+#[derive(Args)]
+#[clap(group(ArgGroup::new("source").required(true)))]
+pub struct NextArgs {
+    #[clap(group = "source", help = "Name of the task")]
+    pub name: Option<String>,
+
+    #[clap(
+        long,
+        group = "source",
+        help = "Repetition pattern to check instead of a task (e.g. 'D=10,20 h=*' or '*/5 * * * *')"
+    )]
+    pub at: Option<String>,
+
+    #[clap(
+        long,
+        requires = "at",
+        help = "IANA time zone the repetition pattern is expressed in (e.g. 'Europe/Paris')"
+    )]
+    pub timezone: Option<String>,
+
+    #[clap(
+        long,
+        requires = "at",
+        help = "Skip the occurrences falling into the exclusion windows of this calendar (can be repeated)"
+    )]
+    pub calendar: Vec<String>,
+
+    #[clap(
+        short = 'n',
+        long,
+        default_value = "10",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Number of occurrences to display"
+    )]
+    pub count: u16,
+
+    #[clap(
+        long,
+        help = "Look for occurrences from this moment (e.g. '2024-03-31 01:00'), instead of now"
+    )]
+    pub from: Option<String>,
+}
+
 #[derive(Args)]
 pub struct RunArgs {
     #[clap(help = "Name of the task to run")]
@@ -96,6 +486,12 @@ pub struct RunArgs {
         help = "Redirect output to the log files instead of STDOUT/STDERR"
     )]
     pub use_log_files: bool,
+
+    #[clap(
+        long,
+        help = "Run even if a previous run is still going, regardless of the task's overlap policy"
+    )]
+    pub force: bool,
 }
 
 #[derive(Args)]
@@ -1,17 +1,29 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+use rand::random;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
-use crate::{service, task::Task};
+use crate::{
+    datetime::get_now,
+    engine::{Limiter, WaitingTask},
+    process::is_process_alive,
+    service,
+    task::Task,
+};
 
 service!(
     daemon (functions) {
         fn hello() -> u32;
+        fn user_id() -> u32;
         fn stop();
         fn reload_tasks();
         fn running_tasks() -> usize;
         fn scheduled() -> super::super::Scheduled;
+        fn run_started(task: Box<crate::task::Task>) -> u64;
+        fn process_spawned(run: (u64, u32));
+        fn run_ended(run_id: u64);
+        fn runs_of(task_id: u64) -> Vec<super::super::RunningTask>;
     }
 );
 
@@ -20,7 +32,8 @@ mod functions {
 
     use crate::sleep::sleep_ms;
 
-    use super::Scheduled;
+    use super::{RunningTask, Scheduled};
+    use crate::task::Task;
 
     pub type State = RwLock<super::State>;
 
@@ -28,6 +41,11 @@ mod functions {
         std::process::id()
     }
 
+    /// Effective ID of the user the daemon runs as
+    pub fn user_id(_: Arc<State>) -> u32 {
+        nix::unistd::geteuid().as_raw()
+    }
+
     pub fn stop(state: Arc<State>) {
         state.write().unwrap().exit = true;
 
@@ -45,7 +63,9 @@ mod functions {
     }
 
     pub fn running_tasks(state: Arc<State>) -> usize {
-        state.read().unwrap().running_tasks.len()
+        let mut state = state.write().unwrap();
+        state.forget_vanished_runs();
+        state.running_tasks.len()
     }
 
     pub fn scheduled(state: Arc<State>) -> Scheduled {
@@ -65,30 +85,47 @@ mod functions {
             sleep_ms(50);
         };
 
+        let mut state = state.write().unwrap();
+        state.forget_vanished_runs();
+
         Scheduled {
             upcoming,
-            running: state
-                .read()
-                .unwrap()
-                .running_tasks
-                .values()
-                .cloned()
-                .collect(),
+            running: state.running_tasks.values().cloned().collect(),
+            waiting: state.limiter.waiting(),
         }
     }
+
+    pub fn run_started(state: Arc<State>, task: Box<Task>) -> u64 {
+        state.write().unwrap().start_run(&task)
+    }
+
+    pub fn process_spawned(state: Arc<State>, (run_id, pid): (u64, u32)) {
+        state.write().unwrap().set_run_pid(run_id, pid);
+    }
+
+    pub fn run_ended(state: Arc<State>, run_id: u64) {
+        state.write().unwrap().end_run(run_id);
+    }
+
+    pub fn runs_of(state: Arc<State>, task_id: u64) -> Vec<RunningTask> {
+        state.write().unwrap().runs_of(task_id)
+    }
 }
 
 pub struct State {
     pub must_reload_tasks: bool,
     pub exit: bool,
     pub exiting: bool,
+    /// Tasks currently running (including manual runs), indexed by run ID
     pub running_tasks: HashMap<u64, RunningTask>,
     pub scheduled_request: Option<Option<Vec<(Task, OffsetDateTime)>>>,
+    pub limiter: Arc<Limiter>,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(limiter: Arc<Limiter>) -> Self {
         Self {
+            limiter,
             must_reload_tasks: false,
             exit: false,
             exiting: false,
@@ -96,16 +133,65 @@ impl State {
             scheduled_request: None,
         }
     }
+
+    /// Register a new run of a task, returning its unique ID
+    pub fn start_run(&mut self, task: &Task) -> u64 {
+        let run_id = random();
+
+        self.running_tasks.insert(
+            run_id,
+            RunningTask {
+                run_id,
+                task: task.clone(),
+                started: get_now(),
+                pid: None,
+            },
+        );
+
+        run_id
+    }
+
+    pub fn set_run_pid(&mut self, run_id: u64, pid: u32) {
+        if let Some(run) = self.running_tasks.get_mut(&run_id) {
+            run.pid = Some(pid);
+        }
+    }
+
+    pub fn end_run(&mut self, run_id: u64) {
+        self.running_tasks.remove(&run_id);
+    }
+
+    /// Forget about runs whose process vanished
+    /// (e.g. a manual run interrupted before it could notify the daemon)
+    pub fn forget_vanished_runs(&mut self) {
+        self.running_tasks
+            .retain(|_, run| run.pid.is_none_or(is_process_alive));
+    }
+
+    pub fn runs_of(&mut self, task_id: u64) -> Vec<RunningTask> {
+        self.forget_vanished_runs();
+
+        self.running_tasks
+            .values()
+            .filter(|run| run.task.id == task_id)
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct RunningTask {
+    pub run_id: u64,
     pub task: Task,
     pub started: OffsetDateTime,
+
+    /// ID of the task's process, once it has been spawned
+    pub pid: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Scheduled {
     pub upcoming: Vec<(Task, OffsetDateTime)>,
     pub running: Vec<RunningTask>,
+    pub waiting: Vec<WaitingTask>,
 }
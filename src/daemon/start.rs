@@ -13,12 +13,12 @@ use once_cell::sync::Lazy;
 use crate::{
     daemon::{
         is_daemon_running,
-        service::{daemon::process, RunningTask, State},
+        service::{daemon::process, State},
         DaemonClient, DaemonStartArgs,
     },
     datetime::get_now,
     debug,
-    engine::start_engine,
+    engine::{start_engine, Limiter, RunTracker},
     error, error_anyhow, info,
     ipc::serve_on_socket,
     logging::PRINT_MESSAGES_DATETIME,
@@ -26,7 +26,6 @@ use crate::{
     save::read_tasks,
     sleep::sleep_ms,
     success,
-    task::Task,
 };
 
 static SOCKET_FILE_PATH: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
@@ -91,21 +90,32 @@ fn daemon_core(paths: &Paths, args: &DaemonStartArgs, socket: UnixListener) -> R
     info!("Successfully started the daemon on {}", get_now());
     info!("Launching a separate thread for the socket listener...");
 
-    let state = Arc::new(RwLock::new(State::new()));
+    // Shared between reloads, as tasks keep running in the meantime
+    let limiter = Arc::new(Limiter::new(&args.engine_args));
+
+    let state = Arc::new(RwLock::new(State::new(Arc::clone(&limiter))));
     let state_server = Arc::clone(&state);
 
     std::thread::spawn(|| serve_on_socket(socket, process, state_server));
 
-    daemon_core_loop(paths, args, state);
+    daemon_core_loop(paths, args, state, limiter);
 
     info!("Daemon exited.");
 
     Ok(())
 }
 
-fn daemon_core_loop(paths: &Paths, args: &DaemonStartArgs, state: Arc<RwLock<State>>) {
+fn daemon_core_loop(
+    paths: &Paths,
+    args: &DaemonStartArgs,
+    state: Arc<RwLock<State>>,
+    limiter: Arc<Limiter>,
+) {
     info!("Starting the engine...");
 
+    // Startup tasks and missed runs are only handled when the daemon starts, not when tasks are reloaded
+    let mut just_started = true;
+
     loop {
         if state.read().unwrap().exit {
             info!("Exiting safely as requested...");
@@ -115,7 +125,11 @@ fn daemon_core_loop(paths: &Paths, args: &DaemonStartArgs, state: Arc<RwLock<Sta
             let mut last_running = 0;
 
             loop {
-                let len = state.read().unwrap().running_tasks.len();
+                let len = {
+                    let mut state = state.write().unwrap();
+                    state.forget_vanished_runs();
+                    state.running_tasks.len()
+                };
 
                 if len == 0 {
                     break;
@@ -153,48 +167,42 @@ fn daemon_core_loop(paths: &Paths, args: &DaemonStartArgs, state: Arc<RwLock<Sta
             state.write().unwrap().must_reload_tasks = false;
         }
 
-        let state_for_marker = Arc::clone(&state);
-
-        let interface = move |task: &Task, running| {
-            let running_tasks = &mut state_for_marker.write().unwrap().running_tasks;
-
-            if running {
-                running_tasks.insert(
-                    task.id,
-                    RunningTask {
-                        task: task.clone(),
-                        started: get_now(),
-                    },
-                );
-            } else {
-                running_tasks.remove(&task.id).unwrap();
-            }
-        };
+        let tracker = Arc::clone(&state) as Arc<dyn RunTracker>;
+
+        start_engine(
+            paths,
+            &tasks,
+            &args.engine_args,
+            just_started,
+            tracker,
+            Arc::clone(&limiter),
+            |scheduled| {
+                if state.read().unwrap().scheduled_request == Some(None) {
+                    let mut state = state.write().unwrap();
+
+                    let scheduled = scheduled
+                        .read()
+                        .unwrap()
+                        .iter()
+                        .map(|(a, b)| {
+                            (
+                                tasks.values().find(|task| task.id == *a).unwrap().clone(),
+                                b.run_at(),
+                            )
+                        })
+                        .collect();
+
+                    state.scheduled_request = Some(Some(scheduled));
+
+                    drop(state);
+                }
 
-        start_engine(paths, &tasks, &args.engine_args, interface, |scheduled| {
-            if state.read().unwrap().scheduled_request == Some(None) {
-                let mut state = state.write().unwrap();
-
-                let scheduled = scheduled
-                    .read()
-                    .unwrap()
-                    .iter()
-                    .map(|(a, b)| {
-                        (
-                            tasks.values().find(|task| task.id == *a).unwrap().clone(),
-                            *b,
-                        )
-                    })
-                    .collect();
-
-                state.scheduled_request = Some(Some(scheduled));
-
-                drop(state);
-            }
+                let state = state.read().unwrap();
+                state.must_reload_tasks || state.exit
+            },
+        );
 
-            let state = state.read().unwrap();
-            state.must_reload_tasks || state.exit
-        });
+        just_started = false;
     }
 }
 
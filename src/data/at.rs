@@ -1,6 +1,6 @@
 use std::{
+    collections::BTreeSet,
     fmt::{Display, Formatter},
-    num::ParseIntError,
 };
 
 use anyhow::{bail, Context, Result};
@@ -8,18 +8,20 @@ use once_cell::sync::Lazy;
 use pomsky_macro::pomsky;
 use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
-use time::OffsetDateTime;
-
-use crate::{datetime::get_now, get_upcoming_moment};
+use time::Weekday;
 
 static AT_STR_PARSER: Lazy<Regex> = Lazy::new(|| {
     Regex::new(pomsky!(
         let sep = ^ | ' ';
-        let every = '*' | [digit]+ (',' [digit]+)*;
+        let num = [digit]+;
+        let every = '*' ('/' num)? | num '-' num ('/' num)? | num (',' num)*;
+        let weekday = [digit] | ['a'-'z']+;
+        let every_weekday = '*' ('/' num)? | weekday '-' weekday ('/' num)? | weekday (',' weekday)*;
 
         Start
         (sep "M=" :months(every))?
         (sep "D=" :days(every))?
+        (sep "W=" :weekdays(every_weekday))?
         (sep "h=" :hours(every))?
         (sep "m=" :minutes(every))?
         (sep "s=" :seconds(every))?
@@ -28,6 +30,35 @@ static AT_STR_PARSER: Lazy<Regex> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Names of the days of the week, indexed by their number of days from sunday
+static WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+static MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Classic cron macros, along with the five-field expression they stand for
+static CRON_MACROS: &[(&str, &str)] = &[
+    ("yearly", "0 0 1 1 *"),
+    ("annually", "0 0 1 1 *"),
+    ("monthly", "0 0 1 * *"),
+    ("weekly", "0 0 * * 0"),
+    ("daily", "0 0 * * *"),
+    ("midnight", "0 0 * * *"),
+    ("hourly", "0 * * * *"),
+];
+
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct At {
     pub hours: Occurrences,
@@ -35,22 +66,49 @@ pub struct At {
     pub seconds: Occurrences,
     pub days: Occurrences,
     pub months: Occurrences,
+
+    /// Days of the week, as a number of days from sunday (0 to 6)
+    ///
+    /// When both this field and `days` are restricted, a date must match both of them
+    /// (e.g. `D=13 W=fri` only matches friday the 13th). Cron expressions follow the
+    /// classic cron behaviour instead, where matching either of them is enough unless
+    /// one of them starts with a wildcard (e.g. `*/2`).
+    #[serde(default)]
+    pub weekdays: Occurrences,
+
+    /// Original expression, when the pattern was provided in the classic cron format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
 }
 
 impl At {
     pub fn parse(at: &str) -> Result<Self> {
+        let at = if Self::is_cron(at) {
+            Self::parse_cron(at)?
+        } else {
+            Self::parse_native(at)?
+        };
+
+        at.check_satisfiable()?;
+
+        Ok(at)
+    }
+
+    fn parse_native(at: &str) -> Result<Self> {
         let capture = AT_STR_PARSER
             .captures(at)
             .context("Invalid repetition format provided")?;
 
-        let months = Self::validate_captured(&capture, "months", 12)?;
-        let days = Self::validate_captured(&capture, "days", 31)?;
-        let hours = Self::validate_captured(&capture, "hours", 23)?;
-        let minutes = Self::validate_captured(&capture, "minutes", 59)?;
-        let seconds = Self::validate_captured(&capture, "seconds", 59)?;
+        let months = Self::validate_captured(&capture, "months", 1, 12)?;
+        let days = Self::validate_captured(&capture, "days", 1, 31)?;
+        let weekdays = Self::parse_captured_weekdays(&capture)?;
+        let hours = Self::validate_captured(&capture, "hours", 0, 23)?;
+        let minutes = Self::validate_captured(&capture, "minutes", 0, 59)?;
+        let seconds = Self::validate_captured(&capture, "seconds", 0, 59)?;
 
         if months.is_none()
             && days.is_none()
+            && weekdays.is_none()
             && hours.is_none()
             && minutes.is_none()
             && seconds.is_none()
@@ -60,38 +118,96 @@ impl At {
 
         Ok(Self {
             seconds: seconds.unwrap_or_else(|| {
-                if months.is_some() || days.is_some() || hours.is_some() || minutes.is_some() {
+                if months.is_some()
+                    || days.is_some()
+                    || weekdays.is_some()
+                    || hours.is_some()
+                    || minutes.is_some()
+                {
                     Occurrences::First
                 } else {
                     Occurrences::Every
                 }
             }),
             minutes: minutes.unwrap_or_else(|| {
-                if months.is_some() || days.is_some() || hours.is_some() {
+                if months.is_some() || days.is_some() || weekdays.is_some() || hours.is_some() {
                     Occurrences::First
                 } else {
                     Occurrences::Every
                 }
             }),
             hours: hours.unwrap_or_else(|| {
-                if months.is_some() || days.is_some() {
+                if months.is_some() || days.is_some() || weekdays.is_some() {
                     Occurrences::First
                 } else {
                     Occurrences::Every
                 }
             }),
             days: days.unwrap_or_else(|| {
-                if months.is_some() {
+                if months.is_some() && weekdays.is_none() {
                     Occurrences::First
                 } else {
                     Occurrences::Every
                 }
             }),
             months: months.unwrap_or(Occurrences::Every),
+            weekdays: weekdays.unwrap_or(Occurrences::Every),
+            cron: None,
+        })
+    }
+
+    fn is_cron(at: &str) -> bool {
+        at.trim_start().starts_with('@')
+            || (!at.contains('=') && at.split_whitespace().count() == 5)
+    }
+
+    fn parse_cron(at: &str) -> Result<Self> {
+        let expr = at.trim();
+
+        let fields = match expr.strip_prefix('@') {
+            Some("reboot") => bail!("Macro '@reboot' does not describe a repetition pattern, use '--on-startup' instead"),
+            Some(name) => CRON_MACROS
+                .iter()
+                .find(|(macro_name, _)| *macro_name == name)
+                .map(|(_, fields)| *fields)
+                .with_context(|| format!("Unknown cron macro: @{name}"))?,
+            None => expr,
+        };
+
+        let fields = fields.split_whitespace().collect::<Vec<_>>();
+
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            bail!(
+                "Cron expressions must have exactly 5 fields (minute, hour, day of month, month, day of week), found {}",
+                fields.len()
+            );
+        };
+
+        let minutes = CronField::MINUTES.parse(minutes)?;
+        let hours = CronField::HOURS.parse(hours)?;
+        let days = CronField::DAYS.parse(days)?;
+        let months = CronField::MONTHS.parse(months)?;
+        let weekdays = CronField::WEEKDAYS.parse(weekdays)?;
+
+        Ok(Self {
+            hours,
+            minutes,
+            seconds: Occurrences::First,
+            days,
+            months,
+            weekdays: match weekdays {
+                Occurrences::Multiple(weekdays) => normalize_weekdays(weekdays),
+                weekdays => weekdays,
+            },
+            cron: Some(expr.to_owned()),
         })
     }
 
     pub fn encode(&self) -> String {
+        if let Some(cron) = &self.cron {
+            return cron.clone();
+        }
+
         let mut out = vec![];
 
         if self.months != Occurrences::Every
@@ -104,15 +220,27 @@ impl At {
 
         if self.days != Occurrences::Every
             || self.months != Occurrences::Every
-            || (self.days == Occurrences::Every && self.hours == Occurrences::First)
+            || (self.days == Occurrences::Every
+                && self.hours == Occurrences::First
+                && self.weekdays == Occurrences::Every)
         {
             if let Some(days) = self.days.encode() {
                 out.push(format!("D={}", days));
             }
         }
 
+        if self.weekdays != Occurrences::Every {
+            if let Some(weekdays) = self
+                .weekdays
+                .encode_with(|day| WEEKDAY_NAMES[usize::from(day % 7)].to_owned())
+            {
+                out.push(format!("W={}", weekdays));
+            }
+        }
+
         if self.hours != Occurrences::Every
             || self.days != Occurrences::Every
+            || self.weekdays != Occurrences::Every
             || self.months != Occurrences::Every
             || (self.hours == Occurrences::Every && self.minutes == Occurrences::First)
         {
@@ -124,6 +252,7 @@ impl At {
         if self.minutes != Occurrences::Every
             || self.hours != Occurrences::Every
             || self.days != Occurrences::Every
+            || self.weekdays != Occurrences::Every
             || self.months != Occurrences::Every
             || (self.minutes == Occurrences::Every && self.seconds == Occurrences::First)
         {
@@ -136,6 +265,7 @@ impl At {
             || self.minutes != Occurrences::Every
             || self.hours != Occurrences::Every
             || self.days != Occurrences::Every
+            || self.weekdays != Occurrences::Every
             || self.months != Occurrences::Every
         {
             if let Some(seconds) = self.seconds.encode() {
@@ -149,6 +279,7 @@ impl At {
     fn validate_captured(
         capture: &Captures,
         name: &'static str,
+        min: u8,
         max: u8,
     ) -> Result<Option<Occurrences>> {
         let group = match capture.name(name) {
@@ -156,10 +287,24 @@ impl At {
             None => return Ok(None),
         };
 
-        let occ = Occurrences::parse(group).unwrap();
+        let occ = Occurrences::parse(group)
+            .with_context(|| format!("Invalid value provided for group '{name}'"))?;
+
+        Self::validate_occurrences(&occ, name, min, max)?;
 
+        Ok(Some(occ))
+    }
+
+    fn validate_occurrences(occ: &Occurrences, name: &'static str, min: u8, max: u8) -> Result<()> {
         let validate = |value: u8| {
-            if value > max {
+            if value < min {
+                bail!(
+                    "The value provided for group '{}' is too low: minimum allowed is {}, found {}",
+                    name,
+                    min,
+                    value
+                )
+            } else if value > max {
                 bail!("The value provided for group '{}' is too high: maximum allowed is {}, found {}", name, max, value)
             } else {
                 Ok(())
@@ -169,58 +314,715 @@ impl At {
         match occ {
             Occurrences::First => unreachable!(),
             Occurrences::Every => {}
-            Occurrences::Once(ref value) => validate(*value)?,
-            Occurrences::Multiple(ref values) => {
+            Occurrences::Once(value) => validate(*value)?,
+            Occurrences::Multiple(values) => {
                 for value in values {
                     validate(*value)?;
                 }
             }
+            Occurrences::Range { from, to, step } => {
+                validate(*from)?;
+                validate(*to)?;
+
+                if from > to {
+                    bail!("Invalid range provided for group '{}': start ({}) is greater than end ({})", name, from, to);
+                }
+
+                if *step == 0 {
+                    bail!("Invalid step provided for group '{}': cannot be zero", name);
+                }
+            }
+            Occurrences::Step(step) => {
+                if *step == 0 {
+                    bail!("Invalid step provided for group '{}': cannot be zero", name);
+                }
+            }
         }
 
+        Ok(())
+    }
+
+    fn parse_captured_weekdays(capture: &Captures) -> Result<Option<Occurrences>> {
+        let group = match capture.name("weekdays") {
+            Some(group) => group.as_str(),
+            None => return Ok(None),
+        };
+
+        let occ = Occurrences::parse_with(group, |day| {
+            match WEEKDAY_NAMES.iter().position(|name| *name == day) {
+                Some(index) => Ok(u8::try_from(index).unwrap()),
+                None => day.parse::<u8>().with_context(|| format!("Invalid day of week '{}': expected a name (mon, tue, ...) or a number between 0 and 7 (sunday being 0 or 7)", day)),
+            }
+        })?;
+
+        Self::validate_occurrences(&occ, "weekdays", 0, 7)?;
+
         Ok(Some(occ))
     }
 
-    pub fn next_occurrence(&self) -> Result<OffsetDateTime> {
-        get_upcoming_moment(get_now(), self)
+    /// Ensure the pattern matches at least one date, as it would otherwise never run
+    pub fn check_satisfiable(&self) -> Result<()> {
+        if self.matches_any_date() {
+            return Ok(());
+        }
+
+        let months = self.months.matched_values(1, 12);
+        let days = self.days.matched_values(1, 31);
+
+        let encoded_months = self.months.encode().unwrap_or_else(|| "1".to_owned());
+        let encoded_days = self.days.encode().unwrap_or_else(|| "1".to_owned());
+
+        let longest = months
+            .iter()
+            .map(|month| max_days_in_month(*month))
+            .max()
+            .unwrap();
+
+        let shortest_day = *days.first().unwrap();
+
+        if shortest_day > longest && !self.matches_any_day_field() {
+            let month_names = months
+                .iter()
+                .map(|month| month_name(*month))
+                .collect::<Vec<_>>();
+
+            bail!(
+                "This pattern never matches: days ({}) and months ({}) conflict, as {} {} at most {} days",
+                encoded_days,
+                encoded_months,
+                month_names.join(", "),
+                if month_names.len() == 1 { "has" } else { "have" },
+                longest
+            );
+        }
+
+        bail!(
+            "This pattern never matches: no date matches days ({}), days of week ({}) and months ({}) at the same time",
+            encoded_days,
+            self.weekdays
+                .encode_with(|day| WEEKDAY_NAMES[usize::from(day % 7)].to_owned())
+                .unwrap(),
+            encoded_months,
+        );
     }
+
+    /// Check if both the days of month and days of week must be matched by a date,
+    /// or if matching any of them is enough (see [`At::weekdays`])
+    /// Check if the pattern matches at least one date
+    ///
+    /// Over a full cycle of the gregorian calendar, every day of every month (including
+    /// february 29th) falls on each day of the week, so only days and months can conflict.
+    pub fn matches_any_date(&self) -> bool {
+        let has_times = !self.hours.matched_values(0, 23).is_empty()
+            && !self.minutes.matched_values(0, 59).is_empty()
+            && !self.seconds.matched_values(0, 59).is_empty();
+
+        let months = self.months.matched_values(1, 12);
+        let days = self.days.matched_values(1, 31);
+
+        let by_day = months
+            .iter()
+            .any(|month| days.iter().any(|day| *day <= max_days_in_month(*month)));
+
+        let by_weekday = !months.is_empty()
+            && [
+                Weekday::Sunday,
+                Weekday::Monday,
+                Weekday::Tuesday,
+                Weekday::Wednesday,
+                Weekday::Thursday,
+                Weekday::Friday,
+                Weekday::Saturday,
+            ]
+            .into_iter()
+            .any(|weekday| self.runs_on_weekday(weekday));
+
+        has_times
+            && if self.matches_any_day_field() {
+                by_day || by_weekday
+            } else {
+                by_day && by_weekday
+            }
+    }
+
+    pub fn matches_any_day_field(&self) -> bool {
+        let Some(cron) = &self.cron else {
+            return false;
+        };
+
+        // Like classic cron implementations, fields starting with a wildcard are considered
+        // unrestricted even when they have a step (macros always have one of them unrestricted)
+        let mut fields = cron.split_whitespace();
+
+        match (fields.nth(2), fields.nth(1)) {
+            (Some(days), Some(weekdays)) => !days.starts_with('*') && !weekdays.starts_with('*'),
+            _ => false,
+        }
+    }
+
+    pub fn runs_on_weekday(&self, weekday: Weekday) -> bool {
+        let day = weekday.number_days_from_sunday();
+
+        match self.weekdays.expand(0, 6) {
+            None => true,
+            // Both 0 and 7 stand for sunday
+            Some(days) => days.iter().any(|value| value % 7 == day),
+        }
+    }
+}
+
+impl At {
+    /// Describe the pattern in plain english (e.g. "at 00:00:00 on the 1st of every month")
+    pub fn describe(&self) -> String {
+        let fixed_times = self.describe_fixed_times();
+
+        let time = fixed_times
+            .clone()
+            .unwrap_or_else(|| self.describe_time_fields());
+
+        let date = self.describe_date();
+
+        match date {
+            Some(date) => format!("{time} {date}"),
+            None if fixed_times.is_some() => format!("{time} every day"),
+            None => time,
+        }
+    }
+
+    /// Describe the times of the day when they are made of fixed values (e.g. "at 09:00:00 and 17:00:00")
+    fn describe_fixed_times(&self) -> Option<String> {
+        let single = |occ: &Occurrences, min: u8| match occ {
+            Occurrences::First => Some(min),
+            Occurrences::Once(value) => Some(*value),
+            _ => None,
+        };
+
+        let minute = single(&self.minutes, 0)?;
+        let second = single(&self.seconds, 0)?;
+
+        let hours = match &self.hours {
+            Occurrences::First | Occurrences::Once(_) | Occurrences::Multiple(_) => {
+                self.hours.matched_values(0, 23)
+            }
+            _ => return None,
+        };
+
+        let times = hours
+            .iter()
+            .map(|hour| format!("{hour:02}:{minute:02}:{second:02}"))
+            .collect::<Vec<_>>();
+
+        Some(format!("at {}", join_english(&times)))
+    }
+
+    /// Describe the times of the day field by field, from the smallest unit to the largest one
+    fn describe_time_fields(&self) -> String {
+        let fields = [
+            (&self.seconds, DescribedField::SECONDS),
+            (&self.minutes, DescribedField::MINUTES),
+            (&self.hours, DescribedField::HOURS),
+        ];
+
+        // Smallest units set to their first value are implied by the larger ones
+        let lowest = fields
+            .iter()
+            .position(|(occ, _)| **occ != Occurrences::First)
+            .unwrap_or(fields.len() - 1);
+
+        let (lowest_occ, lowest_field) = &fields[lowest];
+
+        let mut out = lowest_field.describe_frequency(lowest_occ);
+
+        for (i, (occ, field)) in fields.iter().enumerate().skip(lowest + 1) {
+            let values = match occ {
+                Occurrences::Every => {
+                    // Make it explicit for single values (e.g. "at minute 30 of every hour")
+                    if i == lowest + 1
+                        && matches!(
+                            **lowest_occ,
+                            Occurrences::Once(_) | Occurrences::Multiple(_)
+                        )
+                    {
+                        out.push_str(&format!(" of every {}", field.singular));
+                    }
+
+                    continue;
+                }
+                _ => describe_values(occ, field),
+            };
+
+            out.push_str(if i == lowest + 1 { " during " } else { " of " });
+            out.push_str(&values);
+        }
+
+        out
+    }
+
+    /// Describe the days the pattern runs on, or `None` if it runs every day
+    fn describe_date(&self) -> Option<String> {
+        let days = describe_days(&self.days);
+
+        let weekdays = match self.weekdays {
+            Occurrences::Every => None,
+            _ => Some(describe_values(&self.weekdays, &DescribedField::WEEKDAYS)),
+        };
+
+        let months = match self.months {
+            Occurrences::Every => None,
+            _ => Some(describe_values(&self.months, &DescribedField::MONTHS)),
+        };
+
+        // Days of the month always come with the month they are in (e.g. "on the 1st of every month")
+        let days = days.map(|days| match &months {
+            Some(months) => format!("{days} of {months}"),
+            None => format!("{days} of every month"),
+        });
+
+        let months = || months.as_ref().map(|months| format!(" in {months}"));
+
+        match (days, weekdays) {
+            (None, None) => months().map(|months| format!("every day{months}")),
+            (Some(days), None) => Some(days),
+            (None, Some(weekdays)) => {
+                Some(format!("on {weekdays}{}", months().unwrap_or_default()))
+            }
+            (Some(days), Some(weekdays)) => Some(if self.matches_any_day_field() {
+                format!("{days} or on {weekdays}{}", months().unwrap_or_default())
+            } else {
+                format!("{days} if it falls on {weekdays}")
+            }),
+        }
+    }
+}
+
+/// Names of the days of the week as displayed to the user, indexed by their number of days from sunday
+static WEEKDAY_FULL_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Name of a month (e.g. "January" for 1), or its number if it is out of range
+fn month_name(month: u8) -> String {
+    month
+        .checked_sub(1)
+        .and_then(|index| MONTH_NAMES.get(usize::from(index)))
+        .map_or_else(|| month.to_string(), |name| (*name).to_owned())
 }
 
-#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// Describe the values matched by a field (e.g. "hours 9 through 17" or "every 2nd month")
+fn describe_values(occ: &Occurrences, field: &DescribedField) -> String {
+    let DescribedField {
+        singular,
+        plural,
+        min,
+        labelled,
+        name,
+    } = field;
+
+    let labelled = |label: &str, values: String| {
+        if *labelled {
+            format!("{label} {values}")
+        } else {
+            values
+        }
+    };
+
+    match occ {
+        Occurrences::First => labelled(singular, name(*min)),
+        Occurrences::Every => format!("every {singular}"),
+        Occurrences::Once(value) => labelled(singular, name(*value)),
+        Occurrences::Multiple(values) => labelled(
+            plural,
+            join_english(&values.iter().map(|value| name(*value)).collect::<Vec<_>>()),
+        ),
+        Occurrences::Range { from, to, step: 1 } => {
+            labelled(plural, format!("{} through {}", name(*from), name(*to)))
+        }
+        Occurrences::Range { from, to, step } => format!(
+            "every {} {singular} from {} through {}",
+            ordinal(*step),
+            name(*from),
+            name(*to)
+        ),
+        Occurrences::Step(step) => format!("every {} {singular}", ordinal(*step)),
+    }
+}
+
+/// Field whose values can be described
+struct DescribedField {
+    singular: &'static str,
+    plural: &'static str,
+    min: u8,
+    /// Precede the values with the unit (e.g. "hours 9 through 17" instead of "9 through 17")
+    labelled: bool,
+    name: fn(u8) -> String,
+}
+
+impl DescribedField {
+    const SECONDS: Self = Self::numeric("second", "seconds");
+    const MINUTES: Self = Self::numeric("minute", "minutes");
+    const HOURS: Self = Self::numeric("hour", "hours");
+
+    const WEEKDAYS: Self = Self {
+        singular: "day",
+        plural: "days",
+        min: 0,
+        labelled: false,
+        name: |day| WEEKDAY_FULL_NAMES[usize::from(day % 7)].to_owned(),
+    };
+
+    const MONTHS: Self = Self {
+        singular: "month",
+        plural: "months",
+        min: 1,
+        labelled: false,
+        name: month_name,
+    };
+
+    const fn numeric(singular: &'static str, plural: &'static str) -> Self {
+        Self {
+            singular,
+            plural,
+            min: 0,
+            labelled: true,
+            name: |value| value.to_string(),
+        }
+    }
+
+    /// Describe how often the field matches, when it is the smallest one (e.g. "every 15 minutes")
+    fn describe_frequency(&self, occ: &Occurrences) -> String {
+        match occ {
+            Occurrences::Every => format!("every {}", self.singular),
+            Occurrences::Range { from, to, step } => {
+                format!("every {} from {from} through {to}", self.count(*step))
+            }
+            Occurrences::Step(step) => format!("every {}", self.count(*step)),
+            _ => format!("at {}", describe_values(occ, self)),
+        }
+    }
+
+    fn count(&self, count: u8) -> String {
+        if count == 1 {
+            self.singular.to_owned()
+        } else {
+            format!("{count} {}", self.plural)
+        }
+    }
+}
+
+/// Describe the days of the month, or `None` if every day matches
+fn describe_days(days: &Occurrences) -> Option<String> {
+    Some(match days {
+        Occurrences::Every => return None,
+        Occurrences::First => "on the 1st".to_owned(),
+        Occurrences::Once(day) => format!("on the {}", ordinal(*day)),
+        Occurrences::Multiple(days) => format!(
+            "on the {}",
+            join_english(&days.iter().map(|day| ordinal(*day)).collect::<Vec<_>>())
+        ),
+        Occurrences::Range { from, to, step: 1 } => {
+            format!("from the {} through the {}", ordinal(*from), ordinal(*to))
+        }
+        Occurrences::Range { from, to, step } => format!(
+            "on every {} day from the {} through the {}",
+            ordinal(*step),
+            ordinal(*from),
+            ordinal(*to)
+        ),
+        Occurrences::Step(step) => format!("on every {} day", ordinal(*step)),
+    })
+}
+
+/// Format a number as an english ordinal (e.g. "1st", "12th" or "23rd")
+fn ordinal(value: u8) -> String {
+    let suffix = match (value % 10, value % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+
+    format!("{value}{suffix}")
+}
+
+/// Join a list of items in plain english (e.g. "a, b and c")
+fn join_english(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{} and {last}", init.join(", ")),
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Occurrences {
     First,
+    #[default]
     Every,
     Once(u8),
     Multiple(Vec<u8>),
+    /// Every value between two bounds (included), separated by a step (e.g. `9-17` or `0-30/10`)
+    Range {
+        from: u8,
+        to: u8,
+        step: u8,
+    },
+    /// Every n-th value, starting from the lowest one (e.g. `*/15`)
+    Step(u8),
 }
 
 impl Occurrences {
     pub fn parse(str: &str) -> Result<Self> {
-        if str == "*" {
-            Ok(Self::Every)
-        } else if !str.contains(',') {
-            Ok(Self::Once(str::parse(str)?))
+        Self::parse_with(str, |value| Ok(str::parse(value)?))
+    }
+
+    pub fn parse_with(str: &str, parse_value: impl Fn(&str) -> Result<u8>) -> Result<Self> {
+        let (values, step) = match str.split_once('/') {
+            Some((values, step)) => (values, Some(str::parse(step)?)),
+            None => (str, None),
+        };
+
+        if values == "*" {
+            Ok(match step {
+                None => Self::Every,
+                Some(step) => Self::Step(step),
+            })
+        } else if let Some((from, to)) = values.split_once('-') {
+            Ok(Self::Range {
+                from: parse_value(from)?,
+                to: parse_value(to)?,
+                step: step.unwrap_or(1),
+            })
+        } else if step.is_some() {
+            bail!("Steps can only be used with a wildcard or a range");
+        } else if !values.contains(',') {
+            Ok(Self::Once(parse_value(values)?))
         } else {
             Ok(Self::Multiple(
-                str.split(',')
-                    .map(str::parse)
-                    .collect::<Result<Vec<_>, ParseIntError>>()?,
+                values
+                    .split(',')
+                    .map(&parse_value)
+                    .collect::<Result<Vec<_>>>()?,
             ))
         }
     }
 
     pub fn encode(&self) -> Option<String> {
+        self.encode_with(|num| num.to_string())
+    }
+
+    pub fn encode_with(&self, encode_value: impl Fn(u8) -> String) -> Option<String> {
         match self {
             Self::First => None,
             Self::Every => Some("*".to_string()),
-            Self::Once(num) => Some(num.to_string()),
+            Self::Once(num) => Some(encode_value(*num)),
             Self::Multiple(nums) => Some(
                 nums.iter()
-                    .map(|num| num.to_string())
+                    .map(|num| encode_value(*num))
                     .collect::<Vec<_>>()
                     .join(","),
             ),
+            Self::Range { from, to, step } => Some(if *step == 1 {
+                format!("{}-{}", encode_value(*from), encode_value(*to))
+            } else {
+                format!("{}-{}/{}", encode_value(*from), encode_value(*to), step)
+            }),
+            Self::Step(step) => Some(format!("*/{step}")),
+        }
+    }
+
+    /// Get the sorted list of values matched inside the provided bounds,
+    /// with the first one standing for [`Occurrences::First`]
+    pub fn matched_values(&self, min: u8, max: u8) -> Vec<u8> {
+        let mut values = match self {
+            Self::First => vec![min],
+            Self::Every => (min..=max).collect(),
+            _ => self.expand(min, max).unwrap(),
+        };
+
+        values.sort_unstable();
+        values.dedup();
+        values
+    }
+
+    /// Get the list of values matched inside the provided bounds,
+    /// or `None` if there is no restriction
+    pub fn expand(&self, min: u8, max: u8) -> Option<Vec<u8>> {
+        match self {
+            Self::First | Self::Every => None,
+            Self::Once(value) => Some(vec![*value]),
+            Self::Multiple(values) => Some(values.clone()),
+            Self::Range { from, to, step } => Some((*from..=*to).step_by((*step).into()).collect()),
+            Self::Step(step) => Some((min..=max).step_by((*step).into()).collect()),
+        }
+    }
+}
+
+/// Get the maximum number of days a month can have (including leap years)
+fn max_days_in_month(month: u8) -> u8 {
+    match month {
+        2 => 29,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Build the occurrences for a list of days of week, where both 0 and 7 stand for sunday
+fn normalize_weekdays(weekdays: Vec<u8>) -> Occurrences {
+    let weekdays = weekdays
+        .into_iter()
+        .map(|day| day % 7)
+        .collect::<BTreeSet<_>>();
+
+    match weekdays.len() {
+        0 | 7 => Occurrences::Every,
+        1 => Occurrences::Once(*weekdays.first().unwrap()),
+        _ => Occurrences::Multiple(weekdays.into_iter().collect()),
+    }
+}
+
+/// Description of a field in a classic cron expression
+struct CronField {
+    name: &'static str,
+    min: u8,
+    max: u8,
+    names: &'static [&'static str],
+}
+
+impl CronField {
+    const MINUTES: Self = Self::numeric("minute", 0, 59);
+    const HOURS: Self = Self::numeric("hour", 0, 23);
+    const DAYS: Self = Self::numeric("day of month", 1, 31);
+
+    const MONTHS: Self = Self {
+        name: "month",
+        min: 1,
+        max: 12,
+        names: &[
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ],
+    };
+
+    // Both 0 and 7 stand for sunday
+    const WEEKDAYS: Self = Self {
+        name: "day of week",
+        min: 0,
+        max: 7,
+        names: &WEEKDAY_NAMES,
+    };
+
+    const fn numeric(name: &'static str, min: u8, max: u8) -> Self {
+        Self {
+            name,
+            min,
+            max,
+            names: &[],
+        }
+    }
+
+    fn parse(&self, field: &str) -> Result<Occurrences> {
+        if !field.contains(',') {
+            return self.parse_single(field);
+        }
+
+        let mut values = BTreeSet::new();
+
+        for item in field.split(',') {
+            let (range, step) =
+                match item.split_once('/') {
+                    Some((range, step)) => (
+                        range,
+                        Some(step.parse::<u8>().with_context(|| {
+                            format!("Invalid step in {} field: {step}", self.name)
+                        })?),
+                    ),
+                    None => (item, None),
+                };
+
+            let (start, end) = if range == "*" {
+                (self.min, self.max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (self.value(start)?, self.value(end)?)
+            } else {
+                let value = self.value(range)?;
+                (value, if step.is_some() { self.max } else { value })
+            };
+
+            if start > end {
+                bail!("Invalid range in {} field: {range}", self.name);
+            }
+
+            let step = step.unwrap_or(1);
+
+            if step == 0 {
+                bail!("Step cannot be zero in {} field", self.name);
+            }
+
+            values.extend((start..=end).step_by(step.into()));
+        }
+
+        Ok(if values.len() == usize::from(self.max - self.min + 1) {
+            Occurrences::Every
+        } else if values.len() == 1 {
+            Occurrences::Once(*values.first().unwrap())
+        } else {
+            Occurrences::Multiple(values.into_iter().collect())
+        })
+    }
+
+    /// Parse a field made of a single value, range or step, keeping its compact form
+    fn parse_single(&self, field: &str) -> Result<Occurrences> {
+        let occ = Occurrences::parse_with(field, |value| self.value(value))
+            .or_else(|_| match field.split_once('/') {
+                // Classic cron also accepts 'n/step', which means 'n-max/step'
+                Some((from, step)) => Ok(Occurrences::Range {
+                    from: self.value(from)?,
+                    to: self.max,
+                    step: step.parse()?,
+                }),
+                None => Occurrences::parse_with(field, |value| self.value(value)),
+            })
+            .with_context(|| format!("Invalid {} field: {field}", self.name))?;
+
+        match occ {
+            Occurrences::Range { from, to, step } if from > to || step == 0 => {
+                bail!("Invalid range in {} field: {field}", self.name)
+            }
+            Occurrences::Step(0) => bail!("Step cannot be zero in {} field", self.name),
+            occ => Ok(occ),
         }
     }
+
+    fn value(&self, raw: &str) -> Result<u8> {
+        let value = match self
+            .names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(raw))
+        {
+            Some(index) => u8::try_from(index).unwrap() + self.min,
+            None => raw
+                .parse::<u8>()
+                .with_context(|| format!("Invalid value in {} field: {raw}", self.name))?,
+        };
+
+        if value < self.min || value > self.max {
+            bail!(
+                "The value provided for the {} field is out of range: expected {} to {}, found {}",
+                self.name,
+                self.min,
+                self.max,
+                value
+            );
+        }
+
+        Ok(value)
+    }
 }
 
 impl Display for At {
@@ -228,3 +1030,100 @@ impl Display for At {
         write!(f, "{}", self.encode())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{month_name, At};
+
+    #[test]
+    fn impossible_patterns_are_rejected() {
+        for pattern in ["M=2 D=30", "M=4,6 D=31", "M=2 D=30-31 W=mon", "0 0 31 4 *"] {
+            let err = At::parse(pattern).err().unwrap();
+            assert!(
+                format!("{err}").contains("never matches"),
+                "Unexpected error for '{pattern}': {err}"
+            );
+        }
+
+        for pattern in [
+            "M=2 D=29",
+            "M=2,5 D=31",
+            "D=31",
+            "0 0 30 2 mon",
+            "M=2 D=29 W=mon",
+        ] {
+            assert!(
+                At::parse(pattern).is_ok(),
+                "Rejected valid pattern '{pattern}'"
+            );
+        }
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        for pattern in [
+            "D=0-5", "M=0-3", "M=0,3", "M=0", "D=0", "M=13", "h=24", "W=8",
+        ] {
+            let err = At::parse(pattern).err().unwrap();
+            assert!(
+                format!("{err}").contains("too low") || format!("{err}").contains("too high"),
+                "Unexpected error for '{pattern}': {err}"
+            );
+        }
+
+        for pattern in ["M=1-12", "D=1,31", "h=0 m=0 s=0", "W=0-7"] {
+            assert!(
+                At::parse(pattern).is_ok(),
+                "Rejected valid pattern '{pattern}'"
+            );
+        }
+    }
+
+    #[test]
+    fn patterns_are_described() {
+        for (pattern, description) in [
+            ("D=1 h=0 m=0 s=0", "at 00:00:00 on the 1st of every month"),
+            ("h=9,17", "at 09:00:00 and 17:00:00 every day"),
+            ("m=*/15", "every 15 minutes"),
+            ("m=30", "at minute 30 of every hour"),
+            ("h=9-17 m=30", "at minute 30 during hours 9 through 17"),
+            ("h=9 s=*/10", "every 10 seconds during minute 0 of hour 9"),
+            (
+                "W=mon-fri h=9-17 m=*/15",
+                "every 15 minutes during hours 9 through 17 on Monday through Friday",
+            ),
+            (
+                "D=13 W=fri",
+                "at 00:00:00 on the 13th of every month if it falls on Friday",
+            ),
+            (
+                "0 0 13 * fri",
+                "at 00:00:00 on the 13th of every month or on Friday",
+            ),
+            (
+                "M=1-6/2 D=1,15 h=8",
+                "at 08:00:00 on the 1st and 15th of every 2nd month from January through June",
+            ),
+            ("M=2 D=*", "at 00:00:00 every day in February"),
+            ("D=*/2 h=12", "at 12:00:00 on every 2nd day of every month"),
+            ("@weekly", "at 00:00:00 on Sunday"),
+        ] {
+            assert_eq!(At::parse(pattern).unwrap().describe(), description);
+        }
+    }
+
+    #[test]
+    fn months_are_described() {
+        assert_eq!(
+            At::parse("M=1,3").unwrap().describe(),
+            "at 00:00:00 on the 1st of January and March"
+        );
+
+        assert!(At::parse("M=0,3").is_err());
+
+        // Values which can't be parsed anymore may still be stored
+        assert_eq!(month_name(12), "December");
+        assert_eq!(month_name(0), "0");
+        assert_eq!(month_name(13), "13");
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+use tz::TimeZone;
+
+use crate::{
+    at::At,
+    datetime::{from_wall_clock, load_timezone, local_timezone, parse_datetime},
+    duration::{format_duration, parse_non_zero_duration},
+    get_upcoming_moment,
+};
+
+pub type Calendars = BTreeMap<String, Calendar>;
+
+/// Named set of windows during which the tasks referencing it don't run
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Calendar {
+    pub name: String,
+    pub windows: Vec<Window>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Window {
+    /// Between two moments (e.g. a holiday or a planned maintenance)
+    Range(DateRange),
+
+    /// Starting at moments matching a repetition pattern, for a fixed duration (e.g. a weekly maintenance)
+    Recurring(Box<RecurringWindow>),
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: OffsetDateTime,
+
+    /// End of the window (excluded)
+    pub to: OffsetDateTime,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecurringWindow {
+    /// Moments the window starts at
+    pub at: At,
+
+    /// Number of seconds the window lasts
+    pub duration: u64,
+
+    /// IANA time zone the repetition pattern is expressed in (local time zone if not provided)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    /// Moment the first occurrence starts at (e.g. an event's start), if the pattern doesn't repeat forever in the past
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starts: Option<OffsetDateTime>,
+
+    /// Moment the last occurrence starts at, if the pattern doesn't repeat forever in the future
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<OffsetDateTime>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Time zone loaded on the first check, as looking it up for every checked moment is costly
+    #[serde(skip)]
+    pub loaded_timezone: OnceLock<TimeZone>,
+}
+
+impl RecurringWindow {
+    fn timezone(&self) -> Result<&TimeZone> {
+        if let Some(timezone) = self.loaded_timezone.get() {
+            return Ok(timezone);
+        }
+
+        let timezone = match &self.timezone {
+            Some(timezone) => load_timezone(timezone)?,
+            None => local_timezone(),
+        };
+
+        Ok(self.loaded_timezone.get_or_init(|| timezone))
+    }
+}
+
+impl Calendar {
+    /// Find the window containing the provided moment, along with the moment it ends at
+    pub fn find_window(&self, moment: OffsetDateTime) -> Result<Option<(&Window, OffsetDateTime)>> {
+        for window in &self.windows {
+            if let Some(end) = window.end_if_contains(moment)? {
+                return Ok(Some((window, end)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Parse a calendar from a simple text file, with one window per line:
+    ///
+    /// * `2026-12-25` excludes a whole day
+    /// * `2026-12-24 .. 2026-12-26` or `2026-11-02 22:00 .. 2026-11-03 02:00` excludes a range (dates without time being included)
+    /// * `every W=sun h=2 for 2h` excludes two hours every sunday (cron expressions are accepted as well)
+    ///
+    /// Anything after a `#` is a comment, which is used as the window's label.
+    pub fn parse_simple(name: String, content: &str, timezone: Option<&str>) -> Result<Self> {
+        let tz = match timezone {
+            Some(timezone) => load_timezone(timezone)?,
+            None => local_timezone(),
+        };
+
+        let mut windows = vec![];
+
+        for (i, line) in content.lines().enumerate() {
+            let (line, label) = match line.split_once('#') {
+                Some((line, comment)) => (line.trim(), Some(comment.trim().to_owned())),
+                None => (line.trim(), None),
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let label = label.filter(|label| !label.is_empty());
+
+            let window = parse_simple_window(line, label, timezone, &tz)
+                .with_context(|| format!("Invalid window at line {}", i + 1))?;
+
+            windows.push(window);
+        }
+
+        Ok(Self { name, windows })
+    }
+}
+
+fn parse_simple_window(
+    line: &str,
+    label: Option<String>,
+    timezone: Option<&str>,
+    tz: &TimeZone,
+) -> Result<Window> {
+    if let Some(recurring) = line.strip_prefix("every ") {
+        let (at, duration) = recurring.rsplit_once(" for ").with_context(|| {
+            format!(
+                "Recurring windows must have a duration (e.g. 'every W=sun h=2 for 2h'): {line}"
+            )
+        })?;
+
+        return Ok(Window::Recurring(Box::new(RecurringWindow {
+            at: At::parse(at.trim())?,
+            duration: parse_non_zero_duration(duration.trim())?,
+            timezone: timezone.map(str::to_owned),
+            starts: None,
+            until: None,
+            label,
+            loaded_timezone: OnceLock::new(),
+        })));
+    }
+
+    let (from, to) = line.split_once("..").unwrap_or((line, line));
+
+    let from = match parse_date(from.trim()) {
+        Some(date) => from_wall_clock(date.midnight().assume_utc(), tz),
+        None => parse_datetime(from, tz)?,
+    };
+
+    // Dates without a time are included as a whole
+    let to = match parse_date(to.trim()) {
+        Some(date) => {
+            let next_day = date.next_day().context("Date is too far in the future")?;
+            from_wall_clock(next_day.midnight().assume_utc(), tz)
+        }
+        None => parse_datetime(to, tz)?,
+    };
+
+    if to <= from {
+        bail!("The window's end must come after its start: {line}");
+    }
+
+    Ok(Window::Range(DateRange { from, to, label }))
+}
+
+fn parse_date(input: &str) -> Option<Date> {
+    Date::parse(input, format_description!("[year]-[month]-[day]")).ok()
+}
+
+impl Window {
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Window::Range(DateRange { label, .. }) => label.as_deref(),
+            Window::Recurring(window) => window.label.as_deref(),
+        }
+    }
+
+    /// Get the moment the window ends at, if it contains the provided moment
+    pub fn end_if_contains(&self, moment: OffsetDateTime) -> Result<Option<OffsetDateTime>> {
+        match self {
+            Window::Range(DateRange { from, to, .. }) => {
+                Ok((*from <= moment && moment < *to).then_some(*to))
+            }
+
+            Window::Recurring(window) => {
+                let duration = Duration::seconds(
+                    i64::try_from(window.duration).context("Window's duration is too large")?,
+                );
+
+                // The window contains the moment if it started during its duration before it
+                let mut after = moment
+                    .checked_sub(duration - Duration::seconds(1))
+                    .context("Window's duration is too large")?;
+
+                if let Some(starts) = window.starts {
+                    after = after.max(starts);
+                }
+
+                let start = get_upcoming_moment(after, &window.at, window.timezone()?)?;
+
+                if window.until.is_some_and(|until| start > until) {
+                    return Ok(None);
+                }
+
+                Ok((start <= moment).then_some(start + duration))
+            }
+        }
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Window::Range(DateRange { from, to, .. }) => write!(
+                f,
+                "from {} to {}",
+                from.replace_nanosecond(0).unwrap(),
+                to.replace_nanosecond(0).unwrap()
+            ),
+            Window::Recurring(window) => {
+                let RecurringWindow {
+                    at,
+                    duration,
+                    timezone,
+                    starts,
+                    until,
+                    ..
+                } = window.as_ref();
+
+                write!(
+                    f,
+                    "for {} starting {}",
+                    format_duration(*duration),
+                    at.describe()
+                )?;
+
+                if let Some(starts) = starts {
+                    write!(f, " from {}", starts.replace_nanosecond(0).unwrap())?;
+                }
+
+                if let Some(until) = until {
+                    write!(f, " until {}", until.replace_nanosecond(0).unwrap())?;
+                }
+
+                match timezone {
+                    Some(timezone) => write!(f, " ({timezone})"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use tz::TimeZone;
+
+    use super::{parse_simple_window, Calendar, DateRange, Window};
+
+    #[test]
+    fn simple_files_are_parsed() {
+        let content = "\
+            # Exclusion windows\n\
+            \n\
+            2026-11-02T22:00:00Z .. 2026-11-03T02:00:00Z # Maintenance\n\
+            every W=sun h=2 for 2h #\n";
+
+        let calendar = Calendar::parse_simple("test".to_owned(), content, None).unwrap();
+
+        let [range, recurring] = calendar.windows.as_slice() else {
+            panic!("Expected two windows");
+        };
+
+        assert!(
+            *range
+                == Window::Range(DateRange {
+                    from: datetime!(2026-11-02 22:00 UTC),
+                    to: datetime!(2026-11-03 02:00 UTC),
+                    label: Some("Maintenance".to_owned()),
+                })
+        );
+
+        let Window::Recurring(recurring) = recurring else {
+            panic!("Expected a recurring window");
+        };
+
+        assert_eq!(recurring.duration, 7_200);
+        assert_eq!(recurring.label, None);
+        assert_eq!((recurring.starts, recurring.until), (None, None));
+
+        let err = Calendar::parse_simple("test".to_owned(), "2026-12-25\nnope\n", None)
+            .err()
+            .unwrap();
+
+        assert_eq!(err.to_string(), "Invalid window at line 2");
+    }
+
+    #[test]
+    fn simple_windows_are_parsed() {
+        let utc = TimeZone::utc();
+        let range = |line| match parse_simple_window(line, None, None, &utc).unwrap() {
+            Window::Range(DateRange { from, to, .. }) => (from, to),
+            Window::Recurring(_) => panic!("Expected a range: {line}"),
+        };
+
+        // Dates without a time are included as a whole
+        assert_eq!(
+            range("2026-12-25"),
+            (
+                datetime!(2026-12-25 00:00 UTC),
+                datetime!(2026-12-26 00:00 UTC)
+            )
+        );
+        assert_eq!(
+            range("2026-12-24 .. 2026-12-26"),
+            (
+                datetime!(2026-12-24 00:00 UTC),
+                datetime!(2026-12-27 00:00 UTC)
+            )
+        );
+        assert_eq!(
+            range("2026-11-02 22:00 .. 2026-11-03 02:00"),
+            (
+                datetime!(2026-11-02 22:00 UTC),
+                datetime!(2026-11-03 02:00 UTC)
+            )
+        );
+
+        for invalid in [
+            "2026-12-26 .. 2026-12-24",
+            "2026-11-02 22:00 .. 2026-11-02 22:00",
+            "2026-13-01",
+            "every W=sun h=2",
+            "every W=sun h=2 for 0s",
+            "every W=foo for 2h",
+        ] {
+            assert!(
+                parse_simple_window(invalid, None, None, &utc).is_err(),
+                "{invalid}"
+            );
+        }
+
+        let Window::Recurring(window) =
+            parse_simple_window("every 0 2 * * sun for 2h", None, Some("UTC"), &utc).unwrap()
+        else {
+            panic!("Expected a recurring window");
+        };
+
+        assert_eq!(window.timezone.as_deref(), Some("UTC"));
+
+        // Doesn't depend on the time zones installed on the system
+        window.loaded_timezone.set(utc).unwrap();
+        assert_eq!(
+            Window::Recurring(window)
+                .end_if_contains(datetime!(2026-11-01 03:00 UTC))
+                .unwrap(),
+            Some(datetime!(2026-11-01 04:00 UTC))
+        );
+    }
+}
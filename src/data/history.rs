@@ -1,4 +1,7 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    path::PathBuf,
+};
 
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
@@ -35,6 +38,18 @@ pub struct HistoryEntry {
     pub started_at: OffsetDateTime,
     pub ended_at: OffsetDateTime,
     pub result: TaskResult,
+
+    /// Attempt number, retries coming after the first attempt
+    #[serde(default = "first_attempt")]
+    pub attempt: u32,
+
+    /// What made the task run
+    #[serde(default)]
+    pub trigger: RunTrigger,
+}
+
+fn first_attempt() -> u32 {
+    1
 }
 
 impl HistoryEntry {
@@ -43,10 +58,36 @@ impl HistoryEntry {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub enum RunTrigger {
+    /// The task's schedule
+    #[default]
+    Schedule,
+
+    /// A manual run
+    Manual,
+
+    /// The daemon started
+    Startup,
+
+    /// Another task succeeded
+    After(String),
+
+    /// Another task failed
+    OnFailure(String),
+
+    /// A file changed in a watched path
+    Watch(PathBuf),
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub enum TaskResult {
     Success,
-    Failed { code: Option<i32> },
+    Failed {
+        code: Option<i32>,
+    },
+    /// The task was stopped as it didn't complete in time
+    TimedOut,
 }
 
 impl Display for TaskResult {
@@ -57,6 +98,20 @@ impl Display for TaskResult {
                 None => write!(f, "failed (no exit code)"),
                 Some(code) => write!(f, "failed with code {}", code),
             },
+            TaskResult::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+impl Display for RunTrigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunTrigger::Schedule => write!(f, "schedule"),
+            RunTrigger::Manual => write!(f, "manual"),
+            RunTrigger::Startup => write!(f, "daemon start"),
+            RunTrigger::After(task) => write!(f, "after '{task}'"),
+            RunTrigger::OnFailure(task) => write!(f, "failure of '{task}'"),
+            RunTrigger::Watch(path) => write!(f, "change of '{}'", path.display()),
         }
     }
 }
@@ -0,0 +1,530 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use anyhow::{bail, Context, Result};
+use time::{macros::format_description, Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+use tz::TimeZone;
+
+use crate::{
+    at::At,
+    calendar::{DateRange, RecurringWindow, Window},
+    datetime::{from_wall_clock, load_timezone, local_timezone, to_wall_clock},
+    get_upcoming_moment, warn,
+};
+
+/// Maximum number of occurrences of a recurrence rule limited by a count
+const MAX_COUNT: u32 = 100_000;
+
+/// Import the events of an iCalendar (`.ics`) file as exclusion windows
+///
+/// Events without an explicit time zone are expressed in the provided one. Recurrence rules
+/// are only supported when they repeat every day, week, month or year without any other part
+/// than an end (`UNTIL` or `COUNT`), other events only being imported for their first occurrence.
+pub fn parse_ics(content: &str, timezone: Option<&str>) -> Result<Vec<Window>> {
+    let mut windows = vec![];
+
+    let mut event = None::<Event>;
+
+    // Components nested in events (e.g. alarms) are ignored
+    let mut nested = 0;
+
+    for (name, params, value) in unfold_lines(content) {
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VEVENT") if event.is_none() => event = Some(Event::default()),
+            ("BEGIN", _) if event.is_some() => nested += 1,
+            ("END", "VEVENT") if nested == 0 => {
+                if let Some(event) = event.take() {
+                    let summary = event.summary.clone();
+
+                    if let Some(window) = event.into_window(timezone).with_context(|| {
+                        format!(
+                            "Invalid event '{}'",
+                            summary.as_deref().unwrap_or("<unnamed>")
+                        )
+                    })? {
+                        windows.push(window);
+                    }
+                }
+            }
+            ("END", _) if nested > 0 => nested -= 1,
+            _ if nested > 0 => {}
+            _ => {
+                let Some(event) = &mut event else {
+                    continue;
+                };
+
+                match name.as_str() {
+                    "SUMMARY" => event.summary = Some(unescape(&value)),
+                    "DTSTART" => event.start = Some(IcsTime::parse(&params, &value)?),
+                    "DTEND" => event.end = Some(IcsTime::parse(&params, &value)?),
+                    "DURATION" => event.duration = Some(parse_ics_duration(&value)?),
+                    "RRULE" => event.rrule = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(windows)
+}
+
+/// Split the content lines of a file into their name, parameters and value,
+/// after joining the lines folded over multiple ones
+fn unfold_lines(content: &str) -> Vec<(String, HashMap<String, String>, String)> {
+    let mut lines = Vec::<String>::new();
+
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(folded), Some(last)) => last.push_str(folded),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let mut params = name.split(';');
+            let name = params.next()?.to_uppercase();
+
+            let params = params
+                .filter_map(|param| param.split_once('='))
+                .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_owned()))
+                .collect();
+
+            Some((name, params, value.trim().to_owned()))
+        })
+        .collect()
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+#[derive(Default)]
+struct Event {
+    summary: Option<String>,
+    start: Option<IcsTime>,
+    end: Option<IcsTime>,
+    duration: Option<u64>,
+    rrule: Option<String>,
+}
+
+impl Event {
+    fn into_window(self, timezone: Option<&str>) -> Result<Option<Window>> {
+        let Self {
+            summary,
+            start,
+            end,
+            duration,
+            rrule,
+        } = self;
+
+        let start = start.context("Event has no start date")?;
+
+        // Time zone the event is expressed in, and its name if it isn't the local one
+        let timezone = match &start {
+            IcsTime::DateTime {
+                timezone: Some(name),
+                ..
+            } => Some(name.as_str()),
+            _ => timezone,
+        };
+
+        let from = start.moment(timezone)?;
+
+        let to = match (end, duration) {
+            (Some(end), _) => end.moment(timezone)?,
+            (None, Some(duration)) => i64::try_from(duration)
+                .ok()
+                .and_then(|duration| from.checked_add(Duration::seconds(duration)))
+                .context("The event's duration is too large")?,
+            // All-day events last for the whole day
+            (None, None) if matches!(start, IcsTime::Date(_)) => from + Duration::days(1),
+            (None, None) => {
+                warn!(
+                    "Skipping event '{}' which has no duration.",
+                    summary.as_deref().unwrap_or("<unnamed>")
+                );
+
+                return Ok(None);
+            }
+        };
+
+        if to <= from {
+            bail!("The event's end must come after its start");
+        }
+
+        let Some(rrule) = rrule else {
+            return Ok(Some(Window::Range(DateRange {
+                from,
+                to,
+                label: summary,
+            })));
+        };
+
+        let tz = load(timezone)?;
+
+        let Some((at, end)) = recurrence_pattern(&rrule, to_wall_clock(from, &tz)) else {
+            warn!(
+                "Only the first occurrence of event '{}' is imported, as its recurrence rule is not supported: {}",
+                summary.as_deref().unwrap_or("<unnamed>"),
+                rrule
+            );
+
+            return Ok(Some(Window::Range(DateRange {
+                from,
+                to,
+                label: summary,
+            })));
+        };
+
+        let until = match end {
+            None => None,
+            Some(RecurrenceEnd::Until(until)) => {
+                Some(IcsTime::parse(&HashMap::new(), until)?.moment(timezone)?)
+            }
+            Some(RecurrenceEnd::Count(count)) => Some(nth_occurrence(from, &at, &tz, count)?),
+        };
+
+        Ok(Some(Window::Recurring(Box::new(RecurringWindow {
+            at,
+            duration: u64::try_from((to - from).whole_seconds())?,
+            timezone: timezone.map(str::to_owned),
+            starts: Some(from),
+            until,
+            label: summary,
+            loaded_timezone: OnceLock::new(),
+        }))))
+    }
+}
+
+/// Moment a recurrence rule stops repeating at
+enum RecurrenceEnd<'a> {
+    /// Last moment an occurrence can start at (included)
+    Until(&'a str),
+
+    /// Number of occurrences, including the first one
+    Count(u32),
+}
+
+/// Translate a simple recurrence rule into the repetition pattern of its occurrences' start,
+/// along with the moment it stops repeating at
+fn recurrence_pattern(
+    rrule: &str,
+    start: OffsetDateTime,
+) -> Option<(At, Option<RecurrenceEnd<'_>>)> {
+    let mut freq = None;
+    let mut end = None;
+
+    for part in rrule.split(';') {
+        match part.split_once('=')? {
+            ("FREQ", value) => freq = Some(value),
+            ("UNTIL", value) => end = Some(RecurrenceEnd::Until(value)),
+            ("COUNT", value) => {
+                let count = value.parse().ok().filter(|count| *count > 0)?;
+                end = Some(RecurrenceEnd::Count(count));
+            }
+            ("INTERVAL", "1") | ("WKST", _) => {}
+            _ => return None,
+        }
+    }
+
+    let time = format!(
+        "h={} m={} s={}",
+        start.hour(),
+        start.minute(),
+        start.second()
+    );
+
+    let pattern = match freq? {
+        "DAILY" => time,
+        "WEEKLY" => format!("W={} {time}", start.weekday().number_days_from_sunday()),
+        "MONTHLY" => format!("D={} {time}", start.day()),
+        "YEARLY" => format!("M={} D={} {time}", u8::from(start.month()), start.day()),
+        _ => return None,
+    };
+
+    Some((At::parse(&pattern).ok()?, end))
+}
+
+/// Find the moment the nth occurrence of a pattern starts at, the first one starting at the provided moment
+fn nth_occurrence(first: OffsetDateTime, at: &At, tz: &TimeZone, n: u32) -> Result<OffsetDateTime> {
+    if n > MAX_COUNT {
+        bail!("Recurrence rules repeating more than {MAX_COUNT} times are not supported");
+    }
+
+    let mut moment = first;
+
+    for _ in 1..n {
+        moment = get_upcoming_moment(moment + Duration::seconds(1), at, tz)?;
+    }
+
+    Ok(moment)
+}
+
+enum IcsTime {
+    Date(Date),
+    DateTime {
+        wall_clock: PrimitiveDateTime,
+        timezone: Option<String>,
+    },
+}
+
+impl IcsTime {
+    fn parse(params: &HashMap<String, String>, value: &str) -> Result<Self> {
+        if params.get("VALUE").is_some_and(|kind| kind == "DATE") || value.len() == 8 {
+            return Date::parse(value, format_description!("[year][month][day]"))
+                .map(Self::Date)
+                .with_context(|| format!("Invalid date: {value}"));
+        }
+
+        let (value, timezone) = match value.strip_suffix('Z') {
+            Some(value) => (value, Some("UTC".to_owned())),
+            None => (value, params.get("TZID").cloned()),
+        };
+
+        let wall_clock = PrimitiveDateTime::parse(
+            value,
+            format_description!("[year][month][day]T[hour][minute][second]"),
+        )
+        .with_context(|| format!("Invalid date: {value}"))?;
+
+        Ok(Self::DateTime {
+            wall_clock,
+            timezone,
+        })
+    }
+
+    fn moment(&self, timezone: Option<&str>) -> Result<OffsetDateTime> {
+        let (wall_clock, timezone) = match self {
+            IcsTime::Date(date) => (date.with_time(Time::MIDNIGHT), timezone),
+            IcsTime::DateTime {
+                wall_clock,
+                timezone: own,
+            } => (*wall_clock, own.as_deref().or(timezone)),
+        };
+
+        Ok(from_wall_clock(wall_clock.assume_utc(), &load(timezone)?))
+    }
+}
+
+fn load(timezone: Option<&str>) -> Result<TimeZone> {
+    match timezone {
+        Some("UTC") => Ok(TimeZone::utc()),
+        Some(timezone) => load_timezone(timezone),
+        None => Ok(local_timezone()),
+    }
+}
+
+/// Parse a duration like `P1D` or `PT1H30M` into a number of seconds
+fn parse_ics_duration(input: &str) -> Result<u64> {
+    let Some(duration) = input.strip_prefix('P') else {
+        bail!("Invalid duration: {input}");
+    };
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    let mut in_time = false;
+    let mut has_unit = false;
+
+    for c in duration.chars() {
+        let factor = match (c, in_time) {
+            ('0'..='9', _) => {
+                number.push(c);
+                continue;
+            }
+            ('T', false) => {
+                in_time = true;
+                continue;
+            }
+            ('W', false) => 604_800,
+            ('D', false) => 86_400,
+            ('H', true) => 3_600,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => bail!("Invalid duration: {input}"),
+        };
+
+        let value = number
+            .parse::<u64>()
+            .with_context(|| format!("Invalid duration: {input}"))?;
+
+        total = value
+            .checked_mul(factor)
+            .and_then(|value| total.checked_add(value))
+            .with_context(|| format!("Duration is too large: {input}"))?;
+
+        number.clear();
+        has_unit = true;
+    }
+
+    // Numbers must be followed by their unit
+    if !has_unit || !number.is_empty() {
+        bail!("Invalid duration: {input}");
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::{parse_ics, parse_ics_duration};
+    use crate::calendar::{DateRange, Window};
+
+    fn calendar(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\nVERSION:2.0\n{events}\nEND:VCALENDAR\n")
+    }
+
+    #[test]
+    fn events_are_imported_as_ranges() {
+        let content = calendar(
+            "BEGIN:VEVENT\n\
+             SUMMARY:Maintenance\\, main\n  database\n\
+             DTSTART:20261102T220000Z\n\
+             DTEND:20261103T020000Z\n\
+             BEGIN:VALARM\n\
+             DTSTART:20261102T210000Z\n\
+             DURATION:PT1H\n\
+             END:VALARM\n\
+             END:VEVENT\n\
+             BEGIN:VEVENT\n\
+             SUMMARY:Christmas\n\
+             DTSTART;VALUE=DATE:20261225\n\
+             END:VEVENT\n\
+             BEGIN:VEVENT\n\
+             DTSTART;TZID=UTC:20261231T230000\n\
+             DURATION:PT1H30M\n\
+             END:VEVENT",
+        );
+
+        let windows = parse_ics(&content, Some("UTC")).unwrap();
+
+        assert!(
+            windows
+                == [
+                    Window::Range(DateRange {
+                        from: datetime!(2026-11-02 22:00 UTC),
+                        to: datetime!(2026-11-03 02:00 UTC),
+                        label: Some("Maintenance, main database".to_owned()),
+                    }),
+                    Window::Range(DateRange {
+                        from: datetime!(2026-12-25 00:00 UTC),
+                        to: datetime!(2026-12-26 00:00 UTC),
+                        label: Some("Christmas".to_owned()),
+                    }),
+                    Window::Range(DateRange {
+                        from: datetime!(2026-12-31 23:00 UTC),
+                        to: datetime!(2027-01-01 00:30 UTC),
+                        label: None,
+                    }),
+                ]
+        );
+    }
+
+    #[test]
+    fn invalid_events_are_rejected() {
+        for event in [
+            "DTEND:20261103T020000Z",
+            "DTSTART:20261103T020000Z\nDTEND:20261102T220000Z",
+            "DTSTART:2026-11-02\nDTEND:20261102T220000Z",
+            "DTSTART:20261102T220000Z\nDURATION:P99999999999999D",
+        ] {
+            let content = calendar(&format!("BEGIN:VEVENT\n{event}\nEND:VEVENT"));
+            assert!(parse_ics(&content, Some("UTC")).is_err(), "{event}");
+        }
+
+        // Events with neither an end or a duration are skipped
+        let content = calendar("BEGIN:VEVENT\nDTSTART:20261102T220000Z\nEND:VEVENT");
+        assert!(parse_ics(&content, Some("UTC")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recurring_events_are_bounded() {
+        let content = calendar(
+            "BEGIN:VEVENT\n\
+             SUMMARY:Weekly\n\
+             DTSTART:20261101T020000Z\n\
+             DURATION:PT2H\n\
+             RRULE:FREQ=WEEKLY;COUNT=3\n\
+             END:VEVENT\n\
+             BEGIN:VEVENT\n\
+             SUMMARY:Daily\n\
+             DTSTART:20261101T120000Z\n\
+             DURATION:PT1H\n\
+             RRULE:FREQ=DAILY;UNTIL=20261103T120000Z\n\
+             END:VEVENT",
+        );
+
+        let windows = parse_ics(&content, Some("UTC")).unwrap();
+        let [weekly, daily] = windows.as_slice() else {
+            panic!("Expected two windows");
+        };
+
+        let contains = |window: &Window, moment| window.end_if_contains(moment).unwrap();
+
+        assert_eq!(contains(weekly, datetime!(2026-10-25 02:00 UTC)), None);
+        assert_eq!(
+            contains(weekly, datetime!(2026-11-01 03:59:59 UTC)),
+            Some(datetime!(2026-11-01 04:00 UTC))
+        );
+        assert_eq!(contains(weekly, datetime!(2026-11-01 04:00 UTC)), None);
+        assert_eq!(
+            contains(weekly, datetime!(2026-11-15 02:00 UTC)),
+            Some(datetime!(2026-11-15 04:00 UTC))
+        );
+        assert_eq!(contains(weekly, datetime!(2026-11-22 02:00 UTC)), None);
+
+        assert_eq!(contains(daily, datetime!(2026-10-31 12:30 UTC)), None);
+        assert_eq!(
+            contains(daily, datetime!(2026-11-03 12:30 UTC)),
+            Some(datetime!(2026-11-03 13:00 UTC))
+        );
+        assert_eq!(contains(daily, datetime!(2026-11-04 12:30 UTC)), None);
+    }
+
+    #[test]
+    fn unsupported_recurrence_rules_import_the_first_occurrence() {
+        let content = calendar(
+            "BEGIN:VEVENT\n\
+             DTSTART:20261101T020000Z\n\
+             DURATION:PT2H\n\
+             RRULE:FREQ=WEEKLY;BYDAY=MO,TU\n\
+             END:VEVENT",
+        );
+
+        assert!(
+            parse_ics(&content, Some("UTC")).unwrap()
+                == [Window::Range(DateRange {
+                    from: datetime!(2026-11-01 02:00 UTC),
+                    to: datetime!(2026-11-01 04:00 UTC),
+                    label: None,
+                })]
+        );
+    }
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(parse_ics_duration("PT1H30M").unwrap(), 5_400);
+        assert_eq!(parse_ics_duration("P1DT1S").unwrap(), 86_401);
+        assert_eq!(parse_ics_duration("P2W").unwrap(), 1_209_600);
+
+        for invalid in [
+            "1H",
+            "PT1D",
+            "P1H",
+            "PT",
+            "PTH",
+            "PT5",
+            "P99999999999999999W",
+        ] {
+            assert!(parse_ics_duration(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{duration::format_duration, size::format_size};
+
+/// Limits applied to the process of a task, so it cannot starve the rest of the system
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Niceness, from -20 to 19 (higher values being less favored by the scheduler)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ionice: Option<IoPriority>,
+
+    /// Maximum CPU time, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_time: Option<u64>,
+
+    /// Maximum size of the address space, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u64>,
+
+    /// Maximum number of open files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_files: Option<u64>,
+
+    /// Maximum number of processes of the user the task runs as
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check if applying the limits requires privileges (negative niceness or realtime I/O class)
+    pub fn require_privileges(&self) -> bool {
+        self.nice.is_some_and(|nice| nice < 0)
+            || self
+                .ionice
+                .is_some_and(|ionice| ionice.class == IoClass::Realtime)
+    }
+}
+
+impl Display for ResourceLimits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            nice,
+            ionice,
+            cpu_time,
+            memory,
+            open_files,
+            processes,
+        } = self;
+
+        let limits = [
+            nice.map(|nice| format!("nice {nice}")),
+            ionice.map(|ionice| format!("ionice {ionice}")),
+            cpu_time.map(|secs| format!("{} of CPU time", format_duration(secs))),
+            memory.map(|bytes| format!("{} of memory", format_size(bytes))),
+            open_files.map(|files| format!("{files} open files")),
+            processes.map(|processes| format!("{processes} processes")),
+        ];
+
+        let limits = limits.into_iter().flatten().collect::<Vec<_>>();
+
+        write!(f, "{}", limits.join(", "))
+    }
+}
+
+/// I/O scheduling class and priority
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoPriority {
+    pub class: IoClass,
+
+    /// Priority inside the class, from 0 (highest) to 7 (lowest)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Always served first
+    Realtime,
+
+    /// Served in turns (default)
+    BestEffort,
+
+    /// Only served when no other process needs the disk
+    Idle,
+}
+
+impl IoPriority {
+    /// Parse a priority like `idle`, `best-effort:7` or `realtime:0`
+    pub fn parse(input: &str) -> Result<Self> {
+        let (class, level) = match input.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (input, None),
+        };
+
+        let class = match class {
+            "realtime" => IoClass::Realtime,
+            "best-effort" => IoClass::BestEffort,
+            "idle" => IoClass::Idle,
+            _ => bail!("Unknown I/O scheduling class '{class}' (expected 'realtime', 'best-effort' or 'idle')"),
+        };
+
+        let level = level
+            .map(|level| {
+                level
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|level| *level <= 7)
+                    .with_context(|| format!("Invalid I/O priority (expected 0 to 7): {level}"))
+            })
+            .transpose()?;
+
+        if class == IoClass::Idle && level.is_some() {
+            bail!("The idle I/O scheduling class doesn't have priorities");
+        }
+
+        Ok(Self { class, level })
+    }
+
+    /// Encode the priority the way the `ioprio_set` system call expects it
+    pub fn encode(&self) -> i32 {
+        let class = match self.class {
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+
+        // Processes get the middle priority by default
+        (class << 13) | i32::from(self.level.unwrap_or(4))
+    }
+}
+
+impl Display for IoPriority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let class = match self.class {
+            IoClass::Realtime => "realtime",
+            IoClass::BestEffort => "best-effort",
+            IoClass::Idle => "idle",
+        };
+
+        match self.level {
+            Some(level) => write!(f, "{class}:{level}"),
+            None => write!(f, "{class}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IoClass, IoPriority};
+
+    #[test]
+    fn io_priorities_are_parsed() {
+        let parsed = |input| IoPriority::parse(input).unwrap();
+
+        assert!(
+            parsed("idle")
+                == IoPriority {
+                    class: IoClass::Idle,
+                    level: None
+                }
+        );
+        assert!(
+            parsed("best-effort:7")
+                == IoPriority {
+                    class: IoClass::BestEffort,
+                    level: Some(7)
+                }
+        );
+
+        for input in ["idle", "best-effort", "best-effort:7", "realtime:0"] {
+            assert_eq!(parsed(input).to_string(), input);
+        }
+
+        assert_eq!(parsed("realtime:0").encode(), 1 << 13);
+        assert_eq!(parsed("best-effort").encode(), (2 << 13) | 4);
+        assert_eq!(parsed("idle").encode(), (3 << 13) | 4);
+
+        for invalid in [
+            "",
+            "fast",
+            "idle:3",
+            "realtime:8",
+            "best-effort:-1",
+            "best-effort:",
+        ] {
+            assert!(IoPriority::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,4 +1,8 @@
 pub mod at;
+pub mod calendar;
 pub mod history;
+pub mod ics;
+pub mod limits;
 pub mod paths;
+pub mod schedule;
 pub mod task;
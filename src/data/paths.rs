@@ -6,7 +6,9 @@ pub struct Paths {
     pub tasks_dir: PathBuf,
 
     pub tasks_file: PathBuf,
+    pub tasks_lock_file: PathBuf,
     pub history_file: PathBuf,
+    pub calendars_file: PathBuf,
 
     pub daemon_socket_file: PathBuf,
     pub daemon_log_file: PathBuf,
@@ -18,7 +20,9 @@ impl Paths {
             tasks_dir: data_dir.join("tasks"),
 
             tasks_file: data_dir.join("tasks.json"),
+            tasks_lock_file: data_dir.join("tasks.lock"),
             history_file: data_dir.join("history.json"),
+            calendars_file: data_dir.join("calendars.json"),
 
             daemon_socket_file: data_dir.join("daemon.sock"),
             daemon_log_file: data_dir.join("daemon.log"),
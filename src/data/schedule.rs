@@ -0,0 +1,95 @@
+use std::fmt::{Display, Formatter};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{at::At, duration::format_duration};
+
+/// Describes when a task should run
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Schedule {
+    /// Run at moments matching a repetition pattern
+    At(At),
+
+    /// Run at a fixed interval after the previous run
+    Interval(Interval),
+
+    /// Run a single time
+    Once(OneShot),
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interval {
+    /// Number of seconds between two runs
+    pub every: u64,
+
+    /// Moment of the previous run the interval is counted from
+    pub anchor: IntervalAnchor,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OneShot {
+    /// Moment to run at
+    pub at: OffsetDateTime,
+
+    /// Keep the task once it succeeded, marked as done, instead of removing it
+    #[serde(default)]
+    pub keep: bool,
+
+    /// The task already succeeded
+    #[serde(default)]
+    pub done: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum IntervalAnchor {
+    /// Count from the moment the previous run started
+    Start,
+
+    /// Count from the moment the previous run ended
+    End,
+}
+
+impl Schedule {
+    /// Describe the schedule in plain english (e.g. "at 00:00:00 on the 1st of every month")
+    pub fn describe(&self) -> String {
+        match self {
+            Schedule::At(at) => at.describe(),
+            Schedule::Interval(Interval { every, anchor }) => format!(
+                "every {} after the previous run {}",
+                format_duration(*every),
+                match anchor {
+                    IntervalAnchor::Start => "started",
+                    IntervalAnchor::End => "ended",
+                }
+            ),
+            Schedule::Once(OneShot { at, keep: _, done }) => format!(
+                "once on {}{}",
+                at.replace_nanosecond(0).unwrap(),
+                if *done { " (done)" } else { "" }
+            ),
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::At(at) => write!(f, "{at}"),
+            Schedule::Interval(Interval { every, anchor }) => write!(
+                f,
+                "every {} after {}",
+                format_duration(*every),
+                match anchor {
+                    IntervalAnchor::Start => "start",
+                    IntervalAnchor::End => "end",
+                }
+            ),
+            Schedule::Once(OneShot { at, .. }) => {
+                write!(f, "{}", at.replace_nanosecond(0).unwrap())
+            }
+        }
+    }
+}
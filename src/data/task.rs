@@ -1,11 +1,25 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{Display, Formatter},
+    path::PathBuf,
+};
 
+use anyhow::Result;
+use clap::ValueEnum;
 use once_cell::sync::Lazy;
 use pomsky_macro::pomsky;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use time::OffsetDateTime;
+use tz::TimeZone;
 
-use crate::at::At;
+use crate::{
+    datetime::{load_timezone, local_timezone},
+    duration::format_duration,
+    history::TaskResult,
+    limits::ResourceLimits,
+    schedule::Schedule,
+};
 
 static NAME_VALIDATOR: Lazy<Regex> =
     Lazy::new(|| Regex::new(pomsky!(Start ['a'-'z' 'A'-'Z' '0'-'9' '-' '_']+ End)).unwrap());
@@ -16,13 +30,615 @@ pub type Tasks = BTreeMap<String, Task>;
 pub struct Task {
     pub id: u64,
     pub name: String,
-    pub at: At,
-    pub shell: Option<String>,
-    pub cmd: String,
+
+    /// Paused tasks don't run, until they are resumed
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+
+    /// Moment a paused task resumes at (only paused until resumed if not provided)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_until: Option<OffsetDateTime>,
+
+    /// Moment a paused task was paused at (not provided for tasks paused before it was tracked)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_at: Option<OffsetDateTime>,
+
+    /// Periods the task was paused during since its last run, so the runs planned during them
+    /// are not caught up later
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub past_pauses: Vec<PastPause>,
+
+    /// Moments the task runs at (only runs when triggered by other tasks if not provided)
+    #[serde(alias = "at", default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+
+    /// Shell the command is provided to, as a program followed by its arguments
+    /// (`/bin/sh -c` if not provided)
+    #[serde(default, deserialize_with = "deserialize_shell")]
+    pub shell: Option<Vec<String>>,
+    pub cmd: TaskCommand,
+
+    /// Directory the command runs in (the daemon's one if not provided)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+
+    /// Environment variables to set for the command, in addition to the daemon's ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// File to load environment variables from before each run (overridden by `env`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_file: Option<PathBuf>,
+
+    /// User the command runs as (the daemon's one if not provided)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// Group the command runs as (the user's primary group if not provided)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// Niceness, I/O priority and resource limits of the command
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+
+    /// IANA time zone the schedule is expressed in (local time zone if not provided)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    /// What to do when the task must run while a previous run is still going
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+
+    /// Named slots the task must hold while running, limiting how many tasks use them at the same time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slots: Vec<String>,
+
+    /// Number of seconds after which the task is stopped if it is still running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+
+    /// Run the task again when it fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+
+    /// What to do with the runs missed while the daemon was stopped or the computer was asleep
+    ///
+    /// Tasks registered before catch-up policies existed don't have one, and keep behaving
+    /// like they used to (see [`Task::catch_up_policy`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catch_up: Option<CatchUpPolicy>,
+
+    /// Maximum delay to shift the task's runs by, in seconds, to spread the load across hosts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<u64>,
+
+    /// Run when one of these tasks succeeds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+
+    /// Run when one of these tasks fails (after its last attempt)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_failure: Vec<String>,
+
+    /// Run when files change in these paths
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch: Option<PathWatch>,
+
+    /// Run when the daemon starts, after this delay in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_startup: Option<u64>,
+
+    /// Calendars whose exclusion windows the task doesn't run during
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calendars: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TaskCommand {
+    /// Command line run by the task's shell
+    Shell(String),
+
+    /// Program run directly, followed by its arguments
+    Exec(Vec<String>),
+}
+
+/// Shells used to be stored as a single string, split on spaces
+fn deserialize_shell<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredShell {
+        Argv(Vec<String>),
+        Legacy(String),
+    }
+
+    let shell = Option::<StoredShell>::deserialize(deserializer)?;
+
+    Ok(shell.map(|shell| match shell {
+        StoredShell::Argv(argv) => argv,
+        StoredShell::Legacy(shell) => shell.split(' ').map(str::to_owned).collect(),
+    }))
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlapPolicy {
+    /// Run anyway, alongside the previous run
+    Allow,
+
+    /// Don't run at all
+    #[default]
+    Skip,
+
+    /// Run once the previous run completed
+    Queue,
+
+    /// Stop the previous run, then run
+    KillPrevious,
+}
+
+/// Period a task was paused during
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PastPause {
+    pub from: OffsetDateTime,
+
+    /// End of the pause (excluded)
+    pub to: OffsetDateTime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CatchUpPolicy {
+    /// Forget about missed runs
+    None,
+
+    /// Run a single time if any run was missed
+    RunOnce,
+
+    /// Run once for each missed run
+    RunAll,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathWatch {
+    /// Files or directories to watch (directories are not watched recursively)
+    pub paths: Vec<PathBuf>,
+
+    /// Only consider files whose name matches one of these glob patterns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub globs: Vec<String>,
+
+    /// Number of seconds without any change to wait for before running
+    pub debounce: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+
+    /// Number of seconds to wait before the first retry
+    pub delay: u64,
+
+    pub backoff: Backoff,
+
+    /// Only retry when the task exited with one of these codes (any failure if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_codes: Vec<i32>,
+}
+
+impl RetryPolicy {
+    /// Get the delay before making a new attempt, after the provided one failed
+    /// (returns `None` if the result doesn't warrant a retry)
+    pub fn delay_after(&self, attempt: u32, result: &TaskResult) -> Option<u64> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let retryable = match result {
+            TaskResult::Success => false,
+            TaskResult::Failed { code: Some(code) } => {
+                self.on_codes.is_empty() || self.on_codes.contains(code)
+            }
+            TaskResult::Failed { code: None } | TaskResult::TimedOut => self.on_codes.is_empty(),
+        };
+
+        if !retryable {
+            return None;
+        }
+
+        Some(match self.backoff {
+            Backoff::Fixed => self.delay,
+            Backoff::Exponential => self
+                .delay
+                .saturating_mul(2u64.saturating_pow(attempt - 1))
+                .min(MAX_RETRY_DELAY),
+        })
+    }
+}
+
+/// Longest delay between two attempts when using an exponential backoff (one day)
+const MAX_RETRY_DELAY: u64 = 86_400;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backoff {
+    /// Wait the same delay between all attempts
+    Fixed,
+
+    /// Double the delay after each attempt
+    Exponential,
+}
+
+impl Display for OverlapPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverlapPolicy::Allow => write!(f, "allow"),
+            OverlapPolicy::Skip => write!(f, "skip"),
+            OverlapPolicy::Queue => write!(f, "queue"),
+            OverlapPolicy::KillPrevious => write!(f, "kill-previous"),
+        }
+    }
+}
+
+impl Display for TaskCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskCommand::Shell(cmd) => write!(f, "{cmd}"),
+            TaskCommand::Exec(argv) => write!(f, "{}", shell_words::join(argv)),
+        }
+    }
+}
+
+impl Display for CatchUpPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatchUpPolicy::None => write!(f, "none"),
+            CatchUpPolicy::RunOnce => write!(f, "run-once"),
+            CatchUpPolicy::RunAll => write!(f, "run-all"),
+        }
+    }
 }
 
 impl Task {
     pub fn is_valid_name(name: &str) -> bool {
         NAME_VALIDATOR.is_match(name)
     }
+
+    /// Get the policy for the runs missed while the daemon was stopped (`daemon_stopped`)
+    /// or the computer was asleep
+    ///
+    /// Tasks without a policy forget about the former, and run a single time for the latter.
+    pub fn catch_up_policy(&self, daemon_stopped: bool) -> CatchUpPolicy {
+        match self.catch_up {
+            Some(policy) => policy,
+            None if daemon_stopped => CatchUpPolicy::None,
+            None => CatchUpPolicy::RunOnce,
+        }
+    }
+
+    /// Check if the task is (or was) paused at the provided moment
+    pub fn is_paused_at(&self, moment: OffsetDateTime) -> bool {
+        let paused = !self.enabled
+            && self.paused_at.is_none_or(|at| moment >= at)
+            && self.paused_until.is_none_or(|until| moment < until);
+
+        paused
+            || self
+                .past_pauses
+                .iter()
+                .any(|pause| pause.from <= moment && moment < pause.to)
+    }
+
+    /// Pause the task, until the provided moment if any
+    pub fn pause(&mut self, now: OffsetDateTime, until: Option<OffsetDateTime>) {
+        // Keep track of the previous pause if it ended by itself
+        self.resume(now);
+
+        self.enabled = false;
+        self.paused_at = Some(now);
+        self.paused_until = until;
+    }
+
+    /// Resume the task, keeping track of the period it was paused during
+    pub fn resume(&mut self, now: OffsetDateTime) {
+        if !self.enabled {
+            let from = self.paused_at.unwrap_or(OffsetDateTime::UNIX_EPOCH);
+            let to = self.paused_until.map_or(now, |until| until.min(now));
+
+            if from < to {
+                self.past_pauses.push(PastPause { from, to });
+            }
+        }
+
+        self.enabled = true;
+        self.paused_at = None;
+        self.paused_until = None;
+    }
+
+    /// Forget about the pauses that ended before the provided moment (e.g. the task's last run),
+    /// as no run planned during them can be caught up anymore
+    pub fn forget_pauses_before(&mut self, moment: OffsetDateTime) {
+        self.past_pauses.retain(|pause| pause.to > moment);
+    }
+
+    pub fn timezone(&self) -> Result<TimeZone> {
+        match &self.timezone {
+            Some(name) => load_timezone(name),
+            None => Ok(local_timezone()),
+        }
+    }
+
+    /// Describe when the task runs in plain english
+    pub fn describe(&self) -> String {
+        let mut conditions = vec![];
+
+        if let Some(schedule) = &self.schedule {
+            conditions.push(schedule.describe());
+        }
+
+        if !self.after.is_empty() {
+            conditions.push(format!("after {} succeeded", join_names(&self.after)));
+        }
+
+        if !self.on_failure.is_empty() {
+            conditions.push(format!("after {} failed", join_names(&self.on_failure)));
+        }
+
+        match self.on_startup {
+            None => {}
+            Some(0) => conditions.push("when the daemon starts".to_owned()),
+            Some(delay) => conditions.push(format!(
+                "{} after the daemon starts",
+                format_duration(delay)
+            )),
+        }
+
+        if let Some(PathWatch { paths, globs, .. }) = &self.watch {
+            let paths = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+
+            let mut condition = format!("when files change in {}", join_names(&paths));
+
+            if !globs.is_empty() {
+                condition.push_str(&format!(" (matching {})", join_names(globs)));
+            }
+
+            conditions.push(condition);
+        }
+
+        let conditions = conditions.join(", or ");
+
+        if self.calendars.is_empty() {
+            conditions
+        } else {
+            format!(
+                "{conditions}, except during {}",
+                join_names(&self.calendars)
+            )
+        }
+    }
+
+    /// Tasks this task runs after, whether they succeed or fail
+    pub fn upstreams(&self) -> impl Iterator<Item = &String> {
+        self.after.iter().chain(&self.on_failure)
+    }
+
+    /// Names of the tasks running after this one, whether it succeeds or fails
+    pub fn dependents<'a>(&self, tasks: &'a Tasks) -> Vec<&'a str> {
+        tasks
+            .values()
+            .filter(|other| other.name != self.name)
+            .filter(|other| other.upstreams().any(|upstream| *upstream == self.name))
+            .map(|other| other.name.as_str())
+            .collect()
+    }
+
+    /// Find a dependency cycle this task would create if it was added to the provided tasks
+    ///
+    /// The cycle is returned as the names of the tasks it goes through, each one running after the next one.
+    pub fn find_dependency_cycle(&self, tasks: &Tasks) -> Option<Vec<String>> {
+        let mut path = vec![self.name.clone()];
+        let mut visited = HashSet::new();
+
+        self.find_path_to(&self.name, tasks, &mut path, &mut visited)
+            .then_some(path)
+    }
+
+    /// Depth-first search of a path to a task through the upstream tasks
+    fn find_path_to(
+        &self,
+        target: &str,
+        tasks: &Tasks,
+        path: &mut Vec<String>,
+        visited: &mut HashSet<String>,
+    ) -> bool {
+        for upstream in self.upstreams() {
+            path.push(upstream.clone());
+
+            if upstream == target {
+                return true;
+            }
+
+            if visited.insert(upstream.clone()) {
+                if let Some(task) = tasks.get(upstream) {
+                    if task.find_path_to(target, tasks, path, visited) {
+                        return true;
+                    }
+                }
+            }
+
+            path.pop();
+        }
+
+        false
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Join task names in plain english (e.g. "'a', 'b' or 'c'")
+fn join_names(names: &[String]) -> String {
+    let names = names
+        .iter()
+        .map(|name| format!("'{name}'"))
+        .collect::<Vec<_>>();
+
+    match names.as_slice() {
+        [init @ .., last] if !init.is_empty() => format!("{} or {last}", init.join(", ")),
+        _ => names.concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::{Backoff, RetryPolicy, Task, Tasks};
+    use crate::history::TaskResult;
+
+    fn task() -> Task {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "task",
+            "cmd": "true",
+        }))
+        .unwrap()
+    }
+
+    fn dependent(name: &str, after: &[&str], on_failure: &[&str]) -> Task {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": name,
+            "cmd": "true",
+            "after": after,
+            "on_failure": on_failure,
+        }))
+        .unwrap()
+    }
+
+    fn tasks(tasks: impl IntoIterator<Item = Task>) -> Tasks {
+        tasks
+            .into_iter()
+            .map(|task| (task.name.clone(), task))
+            .collect()
+    }
+
+    #[test]
+    fn dependency_cycles_are_found() {
+        let existing = tasks([
+            dependent("a", &[], &[]),
+            dependent("b", &["a"], &[]),
+            dependent("c", &[], &["b"]),
+        ]);
+
+        assert_eq!(
+            dependent("d", &["c"], &["a"]).find_dependency_cycle(&existing),
+            None
+        );
+
+        // Replacing an existing task
+        assert_eq!(
+            dependent("a", &["c"], &[]).find_dependency_cycle(&existing),
+            Some(vec![
+                "a".to_owned(),
+                "c".to_owned(),
+                "b".to_owned(),
+                "a".to_owned()
+            ])
+        );
+
+        assert_eq!(
+            dependent("a", &[], &["a"]).find_dependency_cycle(&existing),
+            Some(vec!["a".to_owned(), "a".to_owned()])
+        );
+    }
+
+    #[test]
+    fn dependents_are_found() {
+        let existing = tasks([
+            dependent("a", &["a"], &[]),
+            dependent("b", &["a"], &[]),
+            dependent("c", &[], &["a", "b"]),
+        ]);
+
+        assert_eq!(existing["a"].dependents(&existing), ["b", "c"]);
+        assert_eq!(existing["b"].dependents(&existing), ["c"]);
+        assert!(existing["c"].dependents(&existing).is_empty());
+    }
+
+    #[test]
+    fn retry_delays_are_computed() {
+        let mut policy = RetryPolicy {
+            max_attempts: 4,
+            delay: 60,
+            backoff: Backoff::Fixed,
+            on_codes: vec![],
+        };
+
+        let failed = TaskResult::Failed { code: Some(1) };
+
+        assert_eq!(policy.delay_after(1, &failed), Some(60));
+        assert_eq!(policy.delay_after(3, &failed), Some(60));
+        assert_eq!(policy.delay_after(4, &failed), None);
+        assert_eq!(policy.delay_after(1, &TaskResult::Success), None);
+        assert_eq!(policy.delay_after(1, &TaskResult::TimedOut), Some(60));
+
+        policy.backoff = Backoff::Exponential;
+        assert_eq!(policy.delay_after(1, &failed), Some(60));
+        assert_eq!(policy.delay_after(3, &failed), Some(240));
+
+        // Capped to a day
+        policy.max_attempts = 100;
+        assert_eq!(policy.delay_after(99, &failed), Some(86_400));
+
+        policy.on_codes = vec![2];
+        assert_eq!(policy.delay_after(1, &failed), None);
+        assert_eq!(
+            policy.delay_after(1, &TaskResult::Failed { code: Some(2) }),
+            Some(60)
+        );
+        assert_eq!(
+            policy.delay_after(1, &TaskResult::Failed { code: None }),
+            None
+        );
+        assert_eq!(policy.delay_after(1, &TaskResult::TimedOut), None);
+    }
+
+    #[test]
+    fn past_pauses_are_tracked() {
+        let mut task = task();
+
+        task.pause(datetime!(2026-11-01 12:00 UTC), None);
+        task.resume(datetime!(2026-11-01 15:00 UTC));
+
+        // Paused until a given moment, and resumed by itself
+        task.pause(
+            datetime!(2026-11-02 12:00 UTC),
+            Some(datetime!(2026-11-02 13:00 UTC)),
+        );
+
+        assert!(!task.is_paused_at(datetime!(2026-11-01 11:00 UTC)));
+        assert!(task.is_paused_at(datetime!(2026-11-01 12:00 UTC)));
+        assert!(task.is_paused_at(datetime!(2026-11-01 14:00 UTC)));
+        assert!(!task.is_paused_at(datetime!(2026-11-01 15:00 UTC)));
+        assert!(!task.is_paused_at(datetime!(2026-11-02 11:00 UTC)));
+        assert!(task.is_paused_at(datetime!(2026-11-02 12:30 UTC)));
+        assert!(!task.is_paused_at(datetime!(2026-11-02 13:00 UTC)));
+
+        task.pause(datetime!(2026-11-03 12:00 UTC), None);
+        assert!(task.is_paused_at(datetime!(2026-11-02 12:30 UTC)));
+        assert!(task.is_paused_at(datetime!(2026-11-04 12:00 UTC)));
+
+        task.forget_pauses_before(datetime!(2026-11-02 00:00 UTC));
+        assert!(!task.is_paused_at(datetime!(2026-11-01 14:00 UTC)));
+        assert!(task.is_paused_at(datetime!(2026-11-02 12:30 UTC)));
+    }
 }
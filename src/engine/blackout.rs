@@ -0,0 +1,60 @@
+use anyhow::{bail, Result};
+use time::OffsetDateTime;
+
+use crate::calendar::{Calendar, Calendars, Window};
+
+/// Maximum number of exclusion windows to skip when looking for an allowed moment
+const MAX_SKIPPED_WINDOWS: usize = 10_000;
+
+/// Exclusion window a moment falls into
+pub struct Blackout<'a> {
+    pub calendar: &'a Calendar,
+    pub window: &'a Window,
+
+    /// Moment the window ends at (excluded)
+    pub ends_at: OffsetDateTime,
+}
+
+/// Find the exclusion window of the named calendars a moment falls into, if any
+///
+/// Calendars that don't exist (anymore) are ignored.
+pub fn find_blackout<'a>(
+    calendars: &'a Calendars,
+    names: &[String],
+    moment: OffsetDateTime,
+) -> Result<Option<Blackout<'a>>> {
+    for calendar in names.iter().filter_map(|name| calendars.get(name)) {
+        if let Some((window, ends_at)) = calendar.find_window(moment)? {
+            return Ok(Some(Blackout {
+                calendar,
+                window,
+                ends_at,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Move a moment out of the exclusion windows of the named calendars, using `next_after`
+/// to find the next candidate once a window ends
+pub fn skip_blackouts(
+    calendars: &Calendars,
+    names: &[String],
+    moment: OffsetDateTime,
+    next_after: impl Fn(OffsetDateTime) -> Result<OffsetDateTime>,
+) -> Result<OffsetDateTime> {
+    let mut moment = moment;
+
+    for _ in 0..MAX_SKIPPED_WINDOWS {
+        match find_blackout(calendars, names, moment)? {
+            None => return Ok(moment),
+            Some(blackout) => moment = next_after(blackout.ends_at)?,
+        }
+    }
+
+    bail!(
+        "Failed to find a moment outside of the exclusion windows of calendars: {}",
+        names.join(", ")
+    )
+}
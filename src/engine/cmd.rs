@@ -1,5 +1,9 @@
 use clap::Args;
 
+use crate::duration::parse_duration;
+
+use super::limiter::{parse_slot_capacity, SlotCapacity};
+
 #[derive(Args)]
 pub struct EngineArgs {
     #[clap(
@@ -8,4 +12,26 @@ pub struct EngineArgs {
         help = "Display tasks's STDOUT and STDERR directly (bypasses log files)"
     )]
     pub(super) direct_output: bool,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Maximum number of tasks running at the same time"
+    )]
+    pub max_concurrent: Option<u16>,
+
+    #[clap(
+        long,
+        value_parser = parse_slot_capacity,
+        help = "Number of tasks that can hold a slot at the same time (e.g. 'db-backup=2', default is 1)"
+    )]
+    pub slot_capacity: Vec<SlotCapacity>,
+
+    #[clap(
+        long,
+        value_parser = parse_duration,
+        default_value = "30s",
+        help = "Delay before running late tasks after the computer woke up, so capabilities like internet access are available again"
+    )]
+    pub wake_up_delay: u64,
 }
@@ -0,0 +1,92 @@
+use std::fs;
+
+use once_cell::sync::Lazy;
+use time::Duration;
+
+use crate::{task::Task, warn};
+
+/// Files identifying the current host, by order of preference
+static HOST_ID_FILES: &[&str] = &[
+    "/etc/machine-id",
+    "/var/lib/dbus/machine-id",
+    "/proc/sys/kernel/hostname",
+];
+
+/// Seed identifying the current host, so each host gets different but stable jitters
+static HOST_SEED: Lazy<String> = Lazy::new(|| {
+    let seed = HOST_ID_FILES
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|content| content.trim().to_owned())
+        .find(|content| !content.is_empty());
+
+    seed.unwrap_or_else(|| {
+        warn!("Failed to identify the current host, jitters will be the same on all hosts.");
+        String::new()
+    })
+});
+
+/// Get the delay a task's runs are shifted by on the current host
+///
+/// The delay only depends on the host and the task's name, so it stays the same
+/// across daemon restarts and when the task is registered again.
+pub fn jitter_offset(task: &Task) -> Duration {
+    let Some(jitter) = task.jitter else {
+        return Duration::ZERO;
+    };
+
+    let hash = fnv1a(format!("{}\0{}", *HOST_SEED, task.name).as_bytes());
+
+    Duration::seconds(
+        (hash % jitter.saturating_add(1))
+            .try_into()
+            .unwrap_or(i64::MAX),
+    )
+}
+
+/// FNV-1a hash, which unlike the standard library's hasher is guaranteed to never change
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use time::Duration;
+
+    use super::jitter_offset;
+    use crate::task::Task;
+
+    fn task(name: &str, jitter: Option<u64>) -> Task {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": name,
+            "cmd": "true",
+            "jitter": jitter,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn jitters_are_stable_and_bounded() {
+        assert_eq!(jitter_offset(&task("a", None)), Duration::ZERO);
+
+        let offsets = (0..100)
+            .map(|i| {
+                let task = task(&format!("task-{i}"), Some(60));
+                let offset = jitter_offset(&task);
+
+                assert!(offset >= Duration::ZERO && offset <= Duration::minutes(1));
+                assert_eq!(jitter_offset(&task), offset);
+
+                offset
+            })
+            .collect::<HashSet<_>>();
+
+        // Tasks are spread over the whole jitter
+        assert!(offsets.len() > 30);
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::EngineArgs;
+use crate::{
+    datetime::get_now,
+    notice,
+    task::{OverlapPolicy, Task},
+};
+
+/// Limits how many tasks run at the same time, both globally and per named slot
+///
+/// Tasks waiting for their turn are served in the order they arrived, unless
+/// the resources they wait for are not available while a later task's are.
+pub struct Limiter {
+    max_concurrent: Option<usize>,
+    slot_capacities: HashMap<String, usize>,
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    running: usize,
+    slots: HashMap<String, usize>,
+    waiting: Vec<(u64, Task, OffsetDateTime)>,
+    next_ticket: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WaitingTask {
+    pub task: Task,
+    pub since: OffsetDateTime,
+
+    /// Limits preventing the task from running (e.g. "slot 'db-backup'")
+    pub blocked_by: Vec<String>,
+}
+
+impl Limiter {
+    pub fn new(args: &EngineArgs) -> Self {
+        Self {
+            max_concurrent: args.max_concurrent.map(usize::from),
+            slot_capacities: args
+                .slot_capacity
+                .iter()
+                .map(|slot| (slot.name.clone(), slot.capacity))
+                .collect(),
+            state: Mutex::new(LimiterState::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until the task is allowed to run, returning `None` if it is already
+    /// waiting and cannot overlap with itself
+    pub fn acquire(self: &Arc<Self>, task: &Task) -> Option<Permit> {
+        let mut state = self.state.lock().unwrap();
+
+        if task.overlap != OverlapPolicy::Allow
+            && state
+                .waiting
+                .iter()
+                .any(|(_, other, _)| other.id == task.id)
+        {
+            notice!(
+                "Skipping task '{}' as it is already waiting to run.",
+                task.name.bright_yellow()
+            );
+
+            return None;
+        }
+
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+
+        state.waiting.push((ticket, task.clone(), get_now()));
+
+        let mut notified = false;
+
+        loop {
+            // Tasks arrived before this one get their turn first
+            let served_first = state
+                .waiting
+                .iter()
+                .take_while(|(other, _, _)| *other != ticket)
+                .any(|(_, other, _)| self.blocked_by(&state, other).is_empty());
+
+            let blocked_by = self.blocked_by(&state, task);
+
+            if blocked_by.is_empty() && !served_first {
+                break;
+            }
+
+            if !notified && !blocked_by.is_empty() {
+                notice!(
+                    "Task '{}' is waiting for: {}",
+                    task.name.bright_yellow(),
+                    blocked_by.join(", ")
+                );
+
+                notified = true;
+            }
+
+            state = self.released.wait(state).unwrap();
+        }
+
+        state.waiting.retain(|(other, _, _)| *other != ticket);
+        state.running += 1;
+
+        for slot in &task.slots {
+            *state.slots.entry(slot.clone()).or_default() += 1;
+        }
+
+        // Other tasks may be able to run as well
+        self.released.notify_all();
+
+        Some(Permit {
+            limiter: Arc::clone(self),
+            slots: task.slots.clone(),
+        })
+    }
+
+    /// Get the tasks waiting for their turn
+    pub fn waiting(&self) -> Vec<WaitingTask> {
+        let state = self.state.lock().unwrap();
+
+        state
+            .waiting
+            .iter()
+            .map(|(_, task, since)| WaitingTask {
+                task: task.clone(),
+                since: *since,
+                blocked_by: self.blocked_by(&state, task),
+            })
+            .collect()
+    }
+
+    /// List the limits preventing a task from running
+    fn blocked_by(&self, state: &LimiterState, task: &Task) -> Vec<String> {
+        let mut blocked_by = vec![];
+
+        if let Some(max) = self.max_concurrent {
+            if state.running >= max {
+                blocked_by.push("concurrency limit".to_owned());
+            }
+        }
+
+        for slot in &task.slots {
+            let capacity = self.slot_capacities.get(slot).copied().unwrap_or(1);
+
+            if state.slots.get(slot).copied().unwrap_or(0) >= capacity {
+                blocked_by.push(format!("slot '{slot}'"));
+            }
+        }
+
+        blocked_by
+    }
+}
+
+/// Allows a task to run, until dropped
+pub struct Permit {
+    limiter: Arc<Limiter>,
+    slots: Vec<String>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+
+        state.running -= 1;
+
+        for slot in &self.slots {
+            if let Some(count) = state.slots.get_mut(slot) {
+                *count -= 1;
+            }
+        }
+
+        self.limiter.released.notify_all();
+    }
+}
+
+/// Number of tasks that can hold a slot at the same time
+#[derive(Clone)]
+pub struct SlotCapacity {
+    pub name: String,
+    pub capacity: usize,
+}
+
+/// Parse a slot capacity like `db-backup=2`
+pub fn parse_slot_capacity(input: &str) -> Result<SlotCapacity> {
+    let (name, capacity) = input
+        .split_once('=')
+        .context("Expected a slot name and a capacity (e.g. 'db-backup=2')")?;
+
+    if !Task::is_valid_name(name) {
+        bail!("Invalid slot name, only letters, digits, dashes and underscores are allowed");
+    }
+
+    let capacity = capacity
+        .parse::<usize>()
+        .with_context(|| format!("Invalid capacity for slot '{name}': {capacity}"))?;
+
+    if capacity == 0 {
+        bail!("Capacity of slot '{name}' cannot be zero");
+    }
+
+    Ok(SlotCapacity {
+        name: name.to_owned(),
+        capacity,
+    })
+}
@@ -1,48 +1,211 @@
+mod blackout;
 mod cmd;
+mod jitter;
+mod limiter;
 mod runner;
 mod scheduler;
+mod tracker;
 mod upcoming;
+mod watcher;
 
+pub use blackout::{find_blackout, Blackout};
 pub use cmd::*;
-pub use runner::runner;
-pub use scheduler::SharedSchedulerQueue;
-pub use upcoming::get_upcoming_moment;
+pub use jitter::jitter_offset;
+pub use limiter::{Limiter, WaitingTask};
+pub use runner::{runner, RunDetails};
+pub use scheduler::{PlannedRun, SchedulerOptions, SharedSchedulerQueue};
+pub use tracker::{handle_overlap, DaemonRunTracker, RunTracker};
+pub use upcoming::{get_next_run, get_upcoming_allowed_moment, get_upcoming_moment};
+
+use std::{fs, io::ErrorKind, sync::Arc};
+
+use anyhow::{Context, Result};
+use rand::random;
 
 use crate::{
-    error, error_anyhow,
+    calendar::Calendars,
+    datetime::get_now,
+    error_anyhow,
+    history::{History, HistoryEntry, TaskResult},
+    info,
     paths::Paths,
-    sleep::sleep_ms,
+    save::{
+        append_to_history, lock_tasks, read_calendars, read_history_file, read_tasks, write_tasks,
+    },
+    schedule::Schedule,
     task::{Task, Tasks},
 };
 
 use self::scheduler::run_tasks;
 
+/// Run the tasks until asked to stop
+///
+/// When the daemon `just_started`, the startup tasks run first and missed runs are caught up.
 pub fn start_engine(
     paths: &Paths,
     tasks: &Tasks,
     args: &EngineArgs,
-    marker: impl Fn(&Task, bool) + Send + Sync + 'static,
+    just_started: bool,
+    tracker: Arc<dyn RunTracker>,
+    limiter: Arc<Limiter>,
     stop_on: impl Fn(SharedSchedulerQueue) -> bool,
 ) {
     let paths = paths.clone();
 
     let direct_output = args.direct_output;
 
+    let history = match read_history_file(&paths.history_file) {
+        Ok(history) => history.unwrap_or_else(History::empty),
+        Err(err) => {
+            error_anyhow!(err.context("Failed to read history, interval schedules will start over"));
+            History::empty()
+        }
+    };
+
+    let calendars = match read_calendars(&paths) {
+        Ok(calendars) => calendars,
+        Err(err) => {
+            error_anyhow!(
+                err.context("Failed to read calendars, exclusion windows will be ignored")
+            );
+            Calendars::default()
+        }
+    };
+
+    let tracker_for_runner = Arc::clone(&tracker);
+
     run_tasks(
         tasks,
-        move |task| {
-            (marker)(task, true);
+        &history,
+        SchedulerOptions {
+            just_started,
+            wake_up_delay: args.wake_up_delay,
+            calendars,
+        },
+        tracker,
+        limiter,
+        move |task, attempt, trigger, scheduled_at| {
+            let tracker = &tracker_for_runner;
+
+            let run_id = match tracker.run_started(task) {
+                Ok(run_id) => Some(run_id),
+                Err(err) => {
+                    error_anyhow!(err.context("Failed to track the task's run"));
+                    None
+                }
+            };
+
+            let started_at = get_now();
+
+            let result = runner(
+                task,
+                &paths,
+                !direct_output,
+                true,
+                RunDetails {
+                    id: run_id.unwrap_or_else(random),
+                    attempt,
+                    trigger: trigger.clone(),
+                    scheduled_at,
+                },
+                |pid| {
+                    if let Some(run_id) = run_id {
+                        tracker.process_spawned(run_id, pid);
+                    }
+                },
+            );
+
+            if let Some(run_id) = run_id {
+                tracker.run_ended(run_id);
+            }
+
+            match result {
+                Ok(entry) => {
+                    if let Some(Schedule::Once(_)) = task.schedule {
+                        if entry.succeeded() {
+                            if let Err(err) = complete_one_shot(&paths, task) {
+                                error_anyhow!(err.context(format!(
+                                    "Failed to complete one-shot task '{}'",
+                                    task.name
+                                )));
+                            }
+                        }
+                    }
 
-            let result = runner(task, &paths, !direct_output);
+                    Some(entry)
+                }
+                Err(err) => {
+                    error_anyhow!(err.context("Runner failed to run (from Scheduler)"));
 
-            (marker)(task, false);
+                    // Record the failure, so the task is retried and the tasks depending on it are triggered
+                    let entry = HistoryEntry {
+                        task_id: task.id,
+                        task_name: task.name.clone(),
+                        started_at,
+                        ended_at: get_now(),
+                        result: TaskResult::Failed { code: None },
+                        attempt,
+                        trigger,
+                    };
 
-            if let Err(err) = result {
-                error_anyhow!(err.context("Runner failed to run (from Scheduler)"));
-                error!("Now sleeping for 5 seconds...");
-                sleep_ms(5000);
+                    if let Err(err) = append_to_history(&paths.history_file, entry.clone()) {
+                        error_anyhow!(err.context("Failed to record the run's failure"));
+                    }
+
+                    Some(entry)
+                }
             }
         },
         stop_on,
     )
 }
+
+/// Remove a one-shot task that succeeded from the tasks file, or mark it as done
+fn complete_one_shot(paths: &Paths, task: &Task) -> Result<()> {
+    // The tasks may be modified by other commands at the same time
+    let _lock = lock_tasks(paths)?;
+
+    let mut tasks = read_tasks(paths)?;
+
+    // Removing the task would leave the tasks depending on it with a dangling reference
+    let has_dependents = !task.dependents(&tasks).is_empty();
+
+    // The task may have been removed or replaced in the meantime
+    let Some(stored) = tasks
+        .get_mut(&task.name)
+        .filter(|stored| stored.id == task.id)
+    else {
+        return Ok(());
+    };
+
+    let keep = match &mut stored.schedule {
+        Some(Schedule::Once(one_shot)) if one_shot.keep || has_dependents => {
+            one_shot.done = true;
+            true
+        }
+        _ => {
+            tasks.remove(&task.name);
+            false
+        }
+    };
+
+    write_tasks(paths, &tasks)?;
+
+    if keep {
+        info!(
+            "One-shot task '{}' is now marked as done.",
+            task.name.bright_yellow()
+        );
+    } else {
+        // The log file may have been removed in the meantime
+        if let Err(err) = fs::remove_file(paths.task_log_file(&task.name)) {
+            if err.kind() != ErrorKind::NotFound {
+                return Err(err).context("Failed to remove the task's log file");
+            }
+        }
+
+        info!("One-shot task '{}' was removed.", task.name.bright_yellow());
+    }
+
+    Ok(())
+}
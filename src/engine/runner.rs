@@ -1,46 +1,140 @@
 use std::{
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Write},
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::process::CommandExt,
     process::Command,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
 };
 
+use nix::{
+    libc,
+    sys::{
+        resource::{setrlimit, Resource},
+        signal::Signal,
+    },
+    unistd::geteuid,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
 use crate::{
-    datetime::{get_now, get_now_second_precision},
-    history::{HistoryEntry, TaskResult},
+    datetime::{get_now, get_now_second_precision, second_precision},
+    duration::format_duration,
+    env::parse_env_file,
+    error_anyhow,
+    history::{HistoryEntry, RunTrigger, TaskResult},
     info,
+    limits::ResourceLimits,
     paths::Paths,
+    process::{signal_process_group, TERMINATION_GRACE_PERIOD},
     save::append_to_history,
-    task::Task,
+    task::{Task, TaskCommand},
+    users::{ensure_can_switch_to, ensure_user_can_access, find_group, find_user},
+    warn,
 };
 use anyhow::{Context, Result};
 
-pub static DEFAULT_SHELL_CMD: &str = "/bin/sh -c";
+pub static DEFAULT_SHELL: &[&str] = &["/bin/sh", "-c"];
+
+/// Details about a run, some of them being exposed to the task's command
+pub struct RunDetails {
+    /// Unique ID of the run
+    pub id: u64,
+
+    /// Attempt number, retries coming after the first attempt
+    pub attempt: u32,
+
+    /// What made the task run
+    pub trigger: RunTrigger,
+
+    /// Moment the run was planned for
+    pub scheduled_at: OffsetDateTime,
+}
+
+pub fn runner(
+    task: &Task,
+    paths: &Paths,
+    use_log_files: bool,
+    own_process_group: bool,
+    run: RunDetails,
+    on_spawned: impl FnOnce(u32),
+) -> Result<HistoryEntry> {
+    let RunDetails {
+        id: run_id,
+        attempt,
+        trigger,
+        scheduled_at,
+    } = run;
 
-pub fn runner(task: &Task, paths: &Paths, use_log_files: bool) -> Result<HistoryEntry> {
     let global_history_file = &paths.history_file;
 
     let started_at = get_now();
 
     info!(
-        "Starting task '{}' on {}...",
+        "Starting task '{}' on {}{}...",
         task.name.bright_yellow(),
-        started_at.to_string().bright_magenta()
+        started_at.to_string().bright_magenta(),
+        if attempt > 1 {
+            format!(" (attempt {attempt})")
+        } else {
+            String::new()
+        }
     );
 
-    let shell_cmd = task
-        .shell
-        .clone()
-        .unwrap_or_else(|| DEFAULT_SHELL_CMD.to_string());
+    let mut cmd = match &task.cmd {
+        TaskCommand::Shell(line) => {
+            let shell = match &task.shell {
+                Some(shell) => shell.iter().map(String::as_str).collect(),
+                None => DEFAULT_SHELL.to_vec(),
+            };
+
+            let (program, args) = shell.split_first().context("The task's shell is empty")?;
 
-    let mut shell_cmd_parts = shell_cmd.split(' ');
+            let mut cmd = Command::new(program);
+            cmd.args(args).arg(line);
+            cmd
+        }
 
-    let mut cmd = Command::new(shell_cmd_parts.next().unwrap());
+        TaskCommand::Exec(argv) => {
+            let (program, args) = argv.split_first().context("The task's command is empty")?;
 
-    for part in shell_cmd_parts {
-        cmd.arg(part);
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            cmd
+        }
+    };
+
+    if let Some(cwd) = &task.cwd {
+        cmd.current_dir(cwd);
     }
 
-    cmd.arg(&task.cmd);
+    // Limits are applied before dropping privileges, as some of them require them
+    apply_limits(&mut cmd, &task.limits);
+    run_as_task_user(&mut cmd, task)?;
+
+    // Variables from the env file are loaded first, so the task's ones take precedence
+    if let Some(env_file) = &task.env_file {
+        let content = fs::read_to_string(env_file)
+            .with_context(|| format!("Failed to read the env file at: {}", env_file.display()))?;
+
+        cmd.envs(parse_env_file(&content)?);
+    }
+
+    cmd.envs(&task.env);
+
+    cmd.env("CRONY_TASK_NAME", &task.name)
+        .env("CRONY_RUN_ID", run_id.to_string())
+        .env(
+            "CRONY_SCHEDULED_AT",
+            second_precision(scheduled_at)
+                .format(&Rfc3339)
+                .context("Failed to format the run's scheduled moment")?,
+        );
+
+    // Allows stopping the task along with all the processes it spawned
+    if own_process_group {
+        cmd.process_group(0);
+    }
 
     let mut log_file = if use_log_files {
         Some(
@@ -61,6 +155,23 @@ pub fn runner(task: &Task, paths: &Paths, use_log_files: bool) -> Result<History
 
     let mut handle = cmd.spawn().context("Failed to spawn the command")?;
 
+    on_spawned(handle.id());
+
+    let (completed, watchdog) = match task.timeout {
+        Some(timeout) => {
+            let (completed, receiver) = mpsc::channel::<()>();
+            let pid = handle.id();
+            let name = task.name.clone();
+
+            let watchdog = std::thread::spawn(move || {
+                enforce_timeout(&name, pid, Duration::from_secs(timeout), receiver)
+            });
+
+            (Some(completed), Some(watchdog))
+        }
+        None => (None, None),
+    };
+
     drop(cmd);
 
     if let Some(log_file) = &mut log_file {
@@ -95,7 +206,14 @@ pub fn runner(task: &Task, paths: &Paths, use_log_files: bool) -> Result<History
 
     let ended_at = get_now();
 
-    let result = if status.success() {
+    // Let the watchdog know the task completed
+    drop(completed);
+
+    let timed_out = watchdog.is_some_and(|watchdog| watchdog.join().unwrap());
+
+    let result = if timed_out {
+        TaskResult::TimedOut
+    } else if status.success() {
         TaskResult::Success
     } else {
         TaskResult::Failed {
@@ -109,7 +227,8 @@ pub fn runner(task: &Task, paths: &Paths, use_log_files: bool) -> Result<History
         ended_at.to_string().bright_magenta(),
         match result {
             TaskResult::Success => format!("{}", result).bright_green(),
-            TaskResult::Failed { code: _ } => format!("{}", result).bright_red(),
+            TaskResult::Failed { code: _ } | TaskResult::TimedOut =>
+                format!("{}", result).bright_red(),
         }
     );
 
@@ -119,6 +238,8 @@ pub fn runner(task: &Task, paths: &Paths, use_log_files: bool) -> Result<History
         started_at,
         ended_at,
         result,
+        attempt,
+        trigger,
     };
 
     append_to_history(global_history_file, entry.clone()).with_context(|| {
@@ -130,3 +251,133 @@ pub fn runner(task: &Task, paths: &Paths, use_log_files: bool) -> Result<History
 
     Ok(entry)
 }
+
+/// Make the command run as the task's user and group, if any
+///
+/// Users and groups are looked up beforehand, as doing so isn't safe between fork and exec.
+fn run_as_task_user(cmd: &mut Command, task: &Task) -> Result<()> {
+    if task.user.is_none() && task.group.is_none() {
+        return Ok(());
+    }
+
+    let user = task.user.as_deref().map(find_user).transpose()?;
+
+    let gid = match (&task.group, &user) {
+        (Some(group), _) => Some(find_group(group)?.gid),
+        (None, Some(user)) => Some(user.gid),
+        (None, None) => None,
+    };
+
+    if let Some(user) = &user {
+        cmd.env("HOME", &user.dir)
+            .env("SHELL", &user.shell)
+            .env("USER", &user.name)
+            .env("LOGNAME", &user.name);
+    }
+
+    let uid = user.as_ref().map(|user| user.uid);
+
+    ensure_can_switch_to(geteuid(), uid, gid)
+        .with_context(|| format!("Task '{}' cannot run as its user or group", task.name))?;
+
+    if !geteuid().is_root() {
+        // Already running as the right user and group
+        return Ok(());
+    }
+
+    // The env file is read by the daemon, so the user must be allowed to read it by themselves
+    if let (Some(uid), Some(gid)) = (uid, gid) {
+        ensure_user_can_access(uid, gid, task.cwd.as_deref(), task.env_file.as_deref())?;
+    }
+
+    // The standard library drops the supplementary groups and enters the working directory
+    // once privileges are dropped, so the user must be allowed to enter it
+    if let Some(gid) = gid {
+        cmd.gid(gid.as_raw());
+    }
+
+    if let Some(uid) = uid {
+        cmd.uid(uid.as_raw());
+    }
+
+    Ok(())
+}
+
+/// Apply the task's niceness, I/O priority and resource limits to its process
+fn apply_limits(cmd: &mut Command, limits: &ResourceLimits) {
+    if limits.is_empty() {
+        return;
+    }
+
+    let ResourceLimits {
+        nice,
+        ionice,
+        cpu_time,
+        memory,
+        open_files,
+        processes,
+    } = limits.clone();
+
+    // SAFETY: only async-signal-safe system calls are made between fork and exec
+    #[allow(unsafe_code)]
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice.into()) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            if let Some(ionice) = ionice {
+                // 'IOPRIO_WHO_PROCESS' targeting the calling process
+                if libc::syscall(libc::SYS_ioprio_set, 1, 0, ionice.encode()) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            for (resource, limit) in [
+                (Resource::RLIMIT_CPU, cpu_time),
+                (Resource::RLIMIT_AS, memory),
+                (Resource::RLIMIT_NOFILE, open_files),
+                (Resource::RLIMIT_NPROC, processes),
+            ] {
+                if let Some(limit) = limit {
+                    setrlimit(resource, limit, limit)?;
+                }
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Stop the task's processes if it doesn't complete before the timeout,
+/// returning `true` if it had to be stopped
+fn enforce_timeout(name: &str, pid: u32, timeout: Duration, completed: Receiver<()>) -> bool {
+    if completed.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+        return false;
+    }
+
+    warn!(
+        "Task '{}' timed out after {}, stopping it...",
+        name.bright_yellow(),
+        format_duration(timeout.as_secs())
+    );
+
+    if let Err(err) = signal_process_group(pid, Signal::SIGTERM) {
+        error_anyhow!(err);
+    }
+
+    if completed.recv_timeout(TERMINATION_GRACE_PERIOD) == Err(RecvTimeoutError::Timeout) {
+        warn!(
+            "Task '{}' did not exit in time, killing it...",
+            name.bright_yellow()
+        );
+
+        if let Err(err) = signal_process_group(pid, Signal::SIGKILL) {
+            error_anyhow!(err);
+        }
+    }
+
+    true
+}
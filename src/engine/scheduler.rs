@@ -1,36 +1,125 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
 
 use time::OffsetDateTime;
 
 use crate::{
-    datetime::{get_now, get_now_second_precision},
+    calendar::Calendars,
+    datetime::{get_now, get_now_second_precision, second_precision},
+    duration::format_duration,
+    error_anyhow,
+    history::{History, HistoryEntry, RunTrigger},
     info, notice,
+    schedule::{Interval, Schedule},
     sleep::sleep_ms,
-    task::{Task, Tasks},
+    task::{CatchUpPolicy, OverlapPolicy, Task, Tasks},
+    warn,
 };
 
-use super::upcoming::{get_new_upcoming_moment, get_upcoming_moment};
+use super::{
+    blackout::{find_blackout, Blackout},
+    jitter::jitter_offset,
+    limiter::Limiter,
+    tracker::{handle_overlap, RunTracker},
+    upcoming::{add_interval, get_missed_runs, get_next_run},
+    watcher::PathWatcher,
+};
+
+/// Maximum number of missed runs to catch up for a single task
+const MAX_CATCH_UP_RUNS: usize = 100;
+
+/// Maximum delay, in seconds, after which a retry is not planned again when the engine starts
+/// (e.g. when the daemon was stopped in the meantime)
+const MAX_RETRY_LATENESS: i64 = 60;
 
 pub fn run_tasks(
     tasks: &Tasks,
-    task_runner: impl Fn(&Task) + Send + Sync + 'static,
+    history: &History,
+    options: SchedulerOptions,
+    tracker: Arc<dyn RunTracker>,
+    limiter: Arc<Limiter>,
+    task_runner: impl Fn(&Task, u32, RunTrigger, OffsetDateTime) -> Option<HistoryEntry>
+        + Send
+        + Sync
+        + 'static,
     stop_on: impl Fn(SharedSchedulerQueue) -> bool,
 ) {
-    let task_runner = Arc::new(RwLock::new(task_runner));
-
     let now = get_now();
 
     let queue = tasks
         .values()
-        .map(|task| (task.id, get_upcoming_moment(now, &task.at).unwrap()))
+        .filter_map(|task| {
+            let last_run = history.for_task(task.id).last();
+
+            // Retries are only kept in the queue, so they are planned again from the history
+            // unless their moment is long gone
+            let retry = last_run.and_then(|entry| {
+                retry_moment(task, entry)
+                    .filter(|retry_at| (now - *retry_at).whole_seconds() <= MAX_RETRY_LATENESS)
+                    .map(|retry_at| PlannedRun::retry(entry, retry_at))
+            });
+
+            // Runs shifted by the jitter may still be upcoming
+            match get_next_run(
+                now - jitter_offset(task),
+                task,
+                &options.calendars,
+                None,
+                last_run,
+            ) {
+                Ok(planned) => {
+                    let planned = planned.map(|planned| PlannedRun::first_attempt(task, planned));
+
+                    // Same as when planning a retry, it isn't performed if the next run comes first
+                    let planned = match (planned, retry) {
+                        (Some(planned), Some(retry)) if planned.run_at() <= retry.run_at() => {
+                            Some(planned)
+                        }
+                        (planned, retry) => retry.or(planned),
+                    };
+
+                    planned.map(|planned| (task.id, planned))
+                }
+                Err(err) => {
+                    error_anyhow!(err.context(format!(
+                        "Failed to plan task '{}', it will not run",
+                        task.name
+                    )));
+                    None
+                }
+            }
+        })
         .collect::<HashMap<_, _>>();
 
     let queue = Arc::new(RwLock::new(queue));
 
+    let dispatcher = Arc::new(Dispatcher {
+        tasks: tasks.clone(),
+        calendars: options.calendars,
+        queue: Arc::clone(&queue),
+        tracker,
+        limiter,
+        task_runner: Box::new(task_runner),
+        pending: Mutex::new(HashSet::new()),
+        wake_up_delay: options.wake_up_delay,
+    });
+
+    if options.just_started {
+        run_startup_tasks(&dispatcher, tasks);
+        catch_up_missed_runs(&dispatcher, tasks, history, now);
+    }
+
+    // Stops watching when the scheduler stops
+    let _watcher = {
+        let dispatcher = Arc::clone(&dispatcher);
+        PathWatcher::start(tasks, move |task, path| {
+            dispatcher.trigger(task, RunTrigger::Watch(path))
+        })
+    };
+
     let mut last_displayed_planned = None;
 
     let mut short_sleep = |next: Option<OffsetDateTime>| {
@@ -55,16 +144,24 @@ pub fn run_tasks(
             .read()
             .unwrap()
             .iter()
-            .min_by_key(|(_, moment)| **moment)
-            .map(|(a, b)| (*a, *b));
+            .min_by_key(|(_, planned)| planned.run_at())
+            .map(|(a, b)| (*a, b.clone()));
 
-        let (task_id, planned_for) = match nearest {
+        let (
+            task_id,
+            PlannedRun {
+                at: planned_for,
+                jitter,
+                attempt,
+                trigger,
+            },
+        ) = match nearest {
             None => {
                 short_sleep(None);
                 continue;
             }
-            Some((_, planned_for)) if planned_for > now => {
-                short_sleep(Some(planned_for));
+            Some((_, planned)) if planned.run_at() > now => {
+                short_sleep(Some(planned.run_at()));
                 continue;
             }
             Some(nearest) => nearest,
@@ -78,29 +175,503 @@ pub fn run_tasks(
             .unwrap()
             .clone();
 
-        let task_runner = Arc::clone(&task_runner);
+        // Moment the schedule is at, as the jitter shifts the whole schedule
+        let unjittered_now = now - jitter;
+
+        // Patterns are planned again right away so occurrences keep coming while the task is running,
+        // while intervals are counted from the previous run
+        if let Some(Schedule::At(_)) = task.schedule {
+            plan_again(
+                &queue,
+                &task,
+                get_next_run(
+                    unjittered_now,
+                    &task,
+                    &dispatcher.calendars,
+                    Some(planned_for),
+                    None,
+                ),
+            );
+        }
+
+        let late = (unjittered_now - planned_for).whole_seconds();
+
+        if late <= 60 {
+            notice!("Running task '{}' late of {} second(s).", task.name, late);
+            dispatcher.dispatch(task, vec![planned_for], attempt, trigger, 0);
+            continue;
+        }
+
+        notice!(
+            "Task '{}' is late of {} second(s) ; the computer may have been to sleep.",
+            task.name,
+            late
+        );
+
+        // Only patterns can be caught up, while intervals and retries always run once
+        let catch_up = match task.schedule {
+            Some(Schedule::At(_)) if attempt == 1 => task.catch_up_policy(false),
+            _ => CatchUpPolicy::RunOnce,
+        };
+
+        let planned = match catch_up {
+            CatchUpPolicy::None => {
+                notice!(
+                    "Skipping the missed run of task '{}' (catch-up policy: none).",
+                    task.name.bright_yellow()
+                );
+
+                continue;
+            }
+
+            CatchUpPolicy::RunOnce => vec![planned_for],
+
+            CatchUpPolicy::RunAll => {
+                let mut planned = vec![planned_for];
+                planned.extend(missed_runs(
+                    &task,
+                    &dispatcher.calendars,
+                    planned_for,
+                    unjittered_now,
+                    MAX_CATCH_UP_RUNS - 1,
+                ));
+                planned
+            }
+        };
+
+        if dispatcher.wake_up_delay > 0 {
+            notice!(
+                "Waiting {} more seconds to ensure all capabilities (e.g. internet access) are available again.",
+                dispatcher.wake_up_delay
+            );
+        }
+
+        dispatcher.dispatch(task, planned, attempt, trigger, dispatcher.wake_up_delay);
+    }
+}
+
+/// Run the tasks with a startup trigger, after their delay
+fn run_startup_tasks(dispatcher: &Arc<Dispatcher>, tasks: &Tasks) {
+    for task in tasks.values() {
+        let Some(delay) = task.on_startup else {
+            continue;
+        };
+
+        if delay > 0 {
+            notice!(
+                "Task '{}' will run in {} as the daemon just started.",
+                task.name.bright_yellow(),
+                format_duration(delay)
+            );
+        }
+
+        dispatcher.dispatch(
+            task.clone(),
+            vec![get_now_second_precision()],
+            1,
+            RunTrigger::Startup,
+            delay,
+        );
+    }
+}
+
+/// Run the tasks whose occurrences were missed while the daemon was stopped
+fn catch_up_missed_runs(
+    dispatcher: &Arc<Dispatcher>,
+    tasks: &Tasks,
+    history: &History,
+    now: OffsetDateTime,
+) {
+    for task in tasks.values() {
+        let catch_up = task.catch_up_policy(true);
 
-        let late = (now - planned_for).whole_seconds();
+        if catch_up == CatchUpPolicy::None {
+            continue;
+        }
 
-        notice!("Running task '{}' late of {} second(s).", task.name, late);
+        // Without any previous run, there is no way to know which runs were missed
+        let Some(last_run) = history.for_task(task.id).last() else {
+            continue;
+        };
 
-        if late > 60 {
-            notice!("More than 60 seconds late ; the computer may have been to sleep.");
-            notice!("Waiting 30 more seconds to ensure all capabilities (e.g. internet access) are available again.");
+        let mut missed = missed_runs(
+            task,
+            &dispatcher.calendars,
+            last_run.started_at,
+            now,
+            MAX_CATCH_UP_RUNS,
+        );
+
+        // Runs are not missed while the task is paused
+        missed.retain(|moment| !task.is_paused_at(*moment));
+
+        let Some(latest) = missed.last().copied() else {
+            continue;
+        };
+
+        notice!(
+            "Task '{}' missed {} run(s) while the daemon was stopped (catch-up policy: {}).",
+            task.name.bright_yellow(),
+            missed.len(),
+            catch_up
+        );
+
+        let planned = match catch_up {
+            CatchUpPolicy::None => unreachable!(),
+            CatchUpPolicy::RunOnce => vec![latest],
+            CatchUpPolicy::RunAll => missed,
+        };
+
+        dispatcher.dispatch(task.clone(), planned, 1, RunTrigger::Schedule, 0);
+    }
+}
 
-            sleep_ms(30_000);
+/// Get the runs of a task missed between two moments, warning when there are too many of them
+fn missed_runs(
+    task: &Task,
+    calendars: &Calendars,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    limit: usize,
+) -> Vec<OffsetDateTime> {
+    // Fetch one more run to detect if some were left out
+    match get_missed_runs(task, calendars, from, to, limit + 1) {
+        Ok(mut missed) => {
+            if missed.len() > limit {
+                missed.truncate(limit);
+
+                warn!(
+                    "Task '{}' missed more than {} runs, only the first ones will be caught up.",
+                    task.name, limit
+                );
+            }
+
+            missed
+        }
+        Err(err) => {
+            error_anyhow!(err.context(format!(
+                "Failed to find the missed runs of task '{}'",
+                task.name
+            )));
+
+            vec![]
         }
+    }
+}
+
+pub struct SchedulerOptions {
+    /// Run the startup tasks and catch up the runs missed since the last time each task ran
+    pub just_started: bool,
+
+    /// Delay before running late tasks, in seconds
+    pub wake_up_delay: u64,
+
+    /// Calendars the tasks' exclusion windows come from
+    pub calendars: Calendars,
+}
+
+/// Run a task, provided its attempt number, trigger and the moment the run was planned for
+type TaskRunner =
+    Box<dyn Fn(&Task, u32, RunTrigger, OffsetDateTime) -> Option<HistoryEntry> + Send + Sync>;
+
+struct Dispatcher {
+    /// All tasks, to find the ones depending on a task that just ran
+    tasks: Tasks,
+
+    calendars: Calendars,
+
+    queue: SharedSchedulerQueue,
+    tracker: Arc<dyn RunTracker>,
+    limiter: Arc<Limiter>,
+    task_runner: TaskRunner,
+
+    /// Tasks with a run waiting for the previous one to complete
+    pending: Mutex<HashSet<u64>>,
+
+    wake_up_delay: u64,
+}
 
-        let queue = Arc::clone(&queue);
+impl Dispatcher {
+    /// Run a task once for each of the planned moments, one after the other, in a separate thread
+    /// after waiting for the provided delay (in seconds)
+    fn dispatch(
+        self: &Arc<Self>,
+        task: Task,
+        planned: Vec<OffsetDateTime>,
+        attempt: u32,
+        trigger: RunTrigger,
+        delay: u64,
+    ) {
+        let dispatcher = Arc::clone(self);
 
         std::thread::spawn(move || {
-            task_runner.read().unwrap()(&task);
+            if delay > 0 {
+                sleep_ms(delay.saturating_mul(1000));
+            }
+
+            let mut ran = false;
+            let mut entry = None;
+
+            for scheduled_at in &planned {
+                (ran, entry) = dispatcher.run(&task, attempt, trigger.clone(), *scheduled_at);
+            }
+
+            let planned_for = *planned.last().unwrap();
 
-            let planned = get_new_upcoming_moment(get_now(), &task.at, planned_for).unwrap();
+            if let Some(Schedule::Interval(Interval { every, anchor: _ })) = task.schedule {
+                let next = if ran {
+                    get_next_run(
+                        get_now(),
+                        &task,
+                        &dispatcher.calendars,
+                        Some(planned_for),
+                        entry.as_ref(),
+                    )
+                } else {
+                    // Count the interval from the skipped occurrence
+                    add_interval(planned_for, every).map(Some)
+                };
 
-            queue.write().unwrap().insert(task.id, planned);
+                plan_again(&dispatcher.queue, &task, next);
+            }
+
+            if let Some(entry) = entry {
+                let retrying = plan_retry(&dispatcher.queue, &task, &entry);
+                dispatcher.trigger_dependents(&task, &entry, retrying);
+            }
         });
     }
+
+    /// Run the tasks depending on a task that just ran
+    fn trigger_dependents(self: &Arc<Self>, task: &Task, entry: &HistoryEntry, retrying: bool) {
+        for dependent in self.tasks.values() {
+            let trigger = if entry.succeeded() {
+                if !dependent.after.contains(&task.name) {
+                    continue;
+                }
+
+                RunTrigger::After(task.name.clone())
+            } else {
+                // Failures only count once the task won't be retried anymore
+                if retrying || !dependent.on_failure.contains(&task.name) {
+                    continue;
+                }
+
+                RunTrigger::OnFailure(task.name.clone())
+            };
+
+            self.trigger(dependent, trigger);
+        }
+    }
+
+    /// Run a task right away following an event
+    fn trigger(self: &Arc<Self>, task: &Task, trigger: RunTrigger) {
+        notice!(
+            "Triggering task '{}' ({}).",
+            task.name.bright_yellow(),
+            trigger
+        );
+
+        self.dispatch(
+            task.clone(),
+            vec![get_now_second_precision()],
+            1,
+            trigger,
+            0,
+        );
+    }
+
+    /// Run a task, unless its overlap policy prevents it
+    fn run(
+        &self,
+        task: &Task,
+        attempt: u32,
+        trigger: RunTrigger,
+        scheduled_at: OffsetDateTime,
+    ) -> (bool, Option<HistoryEntry>) {
+        if task.is_paused_at(get_now()) {
+            notice!(
+                "Skipping task '{}' as it is paused.",
+                task.name.bright_yellow()
+            );
+
+            return (false, None);
+        }
+
+        match find_blackout(&self.calendars, &task.calendars, get_now()) {
+            Ok(None) => {}
+            Ok(Some(Blackout {
+                calendar, window, ..
+            })) => {
+                notice!(
+                    "Skipping task '{}' as it is in an exclusion window of calendar '{}'{}.",
+                    task.name.bright_yellow(),
+                    calendar.name,
+                    window
+                        .label()
+                        .map(|label| format!(" ({label})"))
+                        .unwrap_or_default()
+                );
+
+                return (false, None);
+            }
+            Err(err) => error_anyhow!(err.context(format!(
+                "Failed to check the exclusion windows of task '{}', running it anyway",
+                task.name
+            ))),
+        }
+
+        let queued = task.overlap == OverlapPolicy::Queue;
+
+        // Only a single run can wait for the previous one to complete
+        let run = if queued && !self.pending.lock().unwrap().insert(task.id) {
+            notice!(
+                "Skipping task '{}' as a run is already waiting for the previous one to complete.",
+                task.name.bright_yellow()
+            );
+
+            false
+        } else {
+            let run = handle_overlap(&*self.tracker, task);
+
+            if queued {
+                self.pending.lock().unwrap().remove(&task.id);
+            }
+
+            run
+        };
+
+        // Wait for the concurrency limit and the task's slots
+        let Some(permit) = (if run {
+            self.limiter.acquire(task)
+        } else {
+            None
+        }) else {
+            return (false, None);
+        };
+
+        let entry = (self.task_runner)(task, attempt, trigger, scheduled_at);
+
+        drop(permit);
+
+        (true, entry)
+    }
 }
 
-pub type SharedSchedulerQueue = Arc<RwLock<HashMap<u64, OffsetDateTime>>>;
+fn plan_again(
+    queue: &SharedSchedulerQueue,
+    task: &Task,
+    next: anyhow::Result<Option<OffsetDateTime>>,
+) {
+    match next {
+        Ok(None) => {}
+        Ok(Some(planned)) => {
+            queue
+                .write()
+                .unwrap()
+                .insert(task.id, PlannedRun::first_attempt(task, planned));
+        }
+        Err(err) => error_anyhow!(err.context(format!(
+            "Failed to plan task '{}' again, it will not run anymore",
+            task.name
+        ))),
+    }
+}
+
+/// Plan a new attempt if the run failed, unless the task's next run comes first,
+/// returning `true` if the task will be retried
+fn plan_retry(queue: &SharedSchedulerQueue, task: &Task, entry: &HistoryEntry) -> bool {
+    let Some(retry_at) = retry_moment(task, entry) else {
+        return false;
+    };
+
+    let mut queue = queue.write().unwrap();
+
+    if let Some(next) = queue.get(&task.id) {
+        if next.run_at() <= retry_at {
+            notice!(
+                "Not retrying task '{}' as it will run again before the retry delay is over.",
+                task.name.bright_yellow()
+            );
+
+            return false;
+        }
+    }
+
+    notice!(
+        "Task '{}' failed, it will be retried on {} (attempt {}).",
+        task.name.bright_yellow(),
+        retry_at.to_string().bright_magenta(),
+        entry.attempt + 1
+    );
+
+    queue.insert(task.id, PlannedRun::retry(entry, retry_at));
+
+    true
+}
+
+/// Get the moment a failed run must be retried at, if it must be
+///
+/// Manual runs are never retried.
+fn retry_moment(task: &Task, entry: &HistoryEntry) -> Option<OffsetDateTime> {
+    if entry.trigger == RunTrigger::Manual {
+        return None;
+    }
+
+    let delay = task
+        .retry
+        .as_ref()
+        .and_then(|retry| retry.delay_after(entry.attempt, &entry.result))?;
+
+    match add_interval(entry.ended_at, delay) {
+        Ok(retry_at) => Some(second_precision(retry_at)),
+        Err(err) => {
+            error_anyhow!(err.context(format!("Failed to plan a retry of task '{}'", task.name)));
+            None
+        }
+    }
+}
+
+pub type SharedSchedulerQueue = Arc<RwLock<HashMap<u64, PlannedRun>>>;
+
+#[derive(Clone)]
+pub struct PlannedRun {
+    /// Moment the run is planned for by the task's schedule
+    pub at: OffsetDateTime,
+
+    /// Delay the run is shifted by on this host
+    pub jitter: time::Duration,
+
+    /// Attempt number, retries coming after the first attempt
+    pub attempt: u32,
+
+    /// What made the task run
+    pub trigger: RunTrigger,
+}
+
+impl PlannedRun {
+    pub fn first_attempt(task: &Task, at: OffsetDateTime) -> Self {
+        Self {
+            at,
+            jitter: jitter_offset(task),
+            attempt: 1,
+            trigger: RunTrigger::Schedule,
+        }
+    }
+
+    /// Plan a new attempt of a failed run
+    pub fn retry(entry: &HistoryEntry, at: OffsetDateTime) -> Self {
+        Self {
+            at,
+            jitter: time::Duration::ZERO,
+            attempt: entry.attempt + 1,
+            // Retries are made on behalf of the failed run
+            trigger: entry.trigger.clone(),
+        }
+    }
+
+    /// Moment the run will actually start
+    pub fn run_at(&self) -> OffsetDateTime {
+        self.at + self.jitter
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    path::Path,
+    sync::{Mutex, RwLock},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use nix::sys::signal::Signal;
+
+use crate::{
+    daemon::{DaemonClient, RunningTask, State},
+    error_anyhow, notice,
+    process::{signal_process_group, TERMINATION_GRACE_PERIOD},
+    sleep::sleep_ms,
+    task::{OverlapPolicy, Task},
+};
+
+/// Keeps track of the running tasks, including the ones started outside of the scheduler
+pub trait RunTracker: Send + Sync {
+    /// Register a new run of a task, returning its unique ID
+    fn run_started(&self, task: &Task) -> Result<u64>;
+
+    fn process_spawned(&self, run_id: u64, pid: u32);
+
+    fn run_ended(&self, run_id: u64);
+
+    fn runs_of(&self, task_id: u64) -> Vec<RunningTask>;
+}
+
+impl RunTracker for RwLock<State> {
+    fn run_started(&self, task: &Task) -> Result<u64> {
+        Ok(self.write().unwrap().start_run(task))
+    }
+
+    fn process_spawned(&self, run_id: u64, pid: u32) {
+        self.write().unwrap().set_run_pid(run_id, pid);
+    }
+
+    fn run_ended(&self, run_id: u64) {
+        self.write().unwrap().end_run(run_id);
+    }
+
+    fn runs_of(&self, task_id: u64) -> Vec<RunningTask> {
+        self.write().unwrap().runs_of(task_id)
+    }
+}
+
+/// Track runs happening outside of the daemon (e.g. manual runs) through the daemon itself
+pub struct DaemonRunTracker {
+    client: Mutex<DaemonClient>,
+}
+
+impl DaemonRunTracker {
+    pub fn connect(socket_path: &Path) -> Result<Self> {
+        Ok(Self {
+            client: Mutex::new(DaemonClient::connect(socket_path)?),
+        })
+    }
+}
+
+impl RunTracker for DaemonRunTracker {
+    fn run_started(&self, task: &Task) -> Result<u64> {
+        self.client
+            .lock()
+            .unwrap()
+            .run_started(Box::new(task.clone()))
+            .context("Failed to notify the daemon the task started")
+    }
+
+    fn process_spawned(&self, run_id: u64, pid: u32) {
+        if let Err(err) = self.client.lock().unwrap().process_spawned((run_id, pid)) {
+            error_anyhow!(err.context("Failed to notify the daemon about the task's process"));
+        }
+    }
+
+    fn run_ended(&self, run_id: u64) {
+        if let Err(err) = self.client.lock().unwrap().run_ended(run_id) {
+            error_anyhow!(err.context("Failed to notify the daemon the task completed"));
+        }
+    }
+
+    fn runs_of(&self, task_id: u64) -> Vec<RunningTask> {
+        self.client
+            .lock()
+            .unwrap()
+            .runs_of(task_id)
+            .unwrap_or_else(|err| {
+                error_anyhow!(err.context("Failed to get the task's runs from the daemon"));
+                vec![]
+            })
+    }
+}
+
+/// Apply the task's overlap policy if a previous run is still going,
+/// returning `false` if the task must not run
+pub fn handle_overlap(tracker: &dyn RunTracker, task: &Task) -> bool {
+    let runs = tracker.runs_of(task.id);
+
+    if runs.is_empty() {
+        return true;
+    }
+
+    match task.overlap {
+        OverlapPolicy::Allow => true,
+
+        OverlapPolicy::Skip => {
+            notice!(
+                "Skipping task '{}' as a previous run is still going.",
+                task.name.bright_yellow()
+            );
+
+            false
+        }
+
+        OverlapPolicy::Queue => {
+            notice!(
+                "Task '{}' will run once its previous run completes.",
+                task.name.bright_yellow()
+            );
+
+            wait_for_runs(tracker, task.id);
+            true
+        }
+
+        OverlapPolicy::KillPrevious => {
+            notice!(
+                "Stopping the previous run of task '{}'...",
+                task.name.bright_yellow()
+            );
+
+            kill_runs(tracker, &runs);
+            wait_for_runs(tracker, task.id);
+            true
+        }
+    }
+}
+
+/// Wait until all runs of a task completed
+fn wait_for_runs(tracker: &dyn RunTracker, task_id: u64) {
+    while !tracker.runs_of(task_id).is_empty() {
+        sleep_ms(500);
+    }
+}
+
+/// Ask runs to exit, and kill them if they don't in time
+fn kill_runs(tracker: &dyn RunTracker, runs: &[RunningTask]) {
+    let signal_all = |runs: &[RunningTask], signal: Signal| {
+        for pid in runs.iter().filter_map(|run| run.pid) {
+            if let Err(err) = signal_process_group(pid, signal) {
+                error_anyhow!(err);
+            }
+        }
+    };
+
+    signal_all(runs, Signal::SIGTERM);
+
+    let started = Instant::now();
+
+    let remaining = || {
+        runs.iter()
+            .filter(|run| {
+                tracker
+                    .runs_of(run.task.id)
+                    .iter()
+                    .any(|other| other.run_id == run.run_id)
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    while started.elapsed() < TERMINATION_GRACE_PERIOD {
+        if remaining().is_empty() {
+            return;
+        }
+
+        sleep_ms(200);
+    }
+
+    signal_all(&remaining(), Signal::SIGKILL);
+}
@@ -1,328 +1,735 @@
-use std::{convert::TryFrom, ops::Add};
+use std::ops::Add;
 
-use anyhow::{Context, Result};
-use time::{Duration, Month, OffsetDateTime};
+use anyhow::{bail, Context, Result};
+use time::{Date, Duration, OffsetDateTime, Time};
+use tz::TimeZone;
 
 use crate::{
-    at::{At, Occurrences},
-    datetime::second_precision,
+    at::At,
+    calendar::Calendars,
+    datetime::{from_wall_clock, second_precision, to_wall_clock},
+    history::HistoryEntry,
+    schedule::{Interval, IntervalAnchor, OneShot, Schedule},
+    task::Task,
 };
 
-// NOTE: This function will fail to run when providing an invalid 'at'
-//  e.g. day = 30 ; month = 2
-pub fn get_upcoming_moment(after: OffsetDateTime, at: &At) -> Result<OffsetDateTime> {
-    let next = after;
-    let global_at = at;
+use super::blackout::{find_blackout, skip_blackouts};
+
+/// Number of years to look into when searching for a matching date, which covers
+/// a full cycle of the gregorian calendar (leap years and days of week included)
+const SEARCH_YEARS: i32 = 400;
+
+/// Get the next moment matching a repetition pattern, which is expressed in the provided time zone
+///
+/// Daylight saving time transitions are handled as follows:
+///
+/// * Wall clock times skipped by a transition (e.g. 02:30 when clocks go from 02:00 to 03:00)
+///   run just after it, shifted by the transition's length (here, at 03:30)
+/// * Wall clock times repeated by a transition (e.g. 02:30 when clocks go from 03:00 back to 02:00)
+///   only run once, on their first occurrence
+pub fn get_upcoming_moment(
+    after: OffsetDateTime,
+    at: &At,
+    timezone: &TimeZone,
+) -> Result<OffsetDateTime> {
+    let after = second_precision(after);
 
-    let next = match &at.seconds {
-        Occurrences::First => {
-            if next.second() == 0 {
-                next
-            } else {
-                next.replace_second(0).unwrap().add(Duration::minutes(1))
-            }
-        }
-        Occurrences::Every => next, //.add(Duration::seconds(1)),
-        Occurrences::Once(at) => {
-            if *at > next.second() {
-                next.replace_second(*at).unwrap()
-            } else {
-                next.replace_second(*at).unwrap().add(Duration::minutes(1))
-            }
-        }
-        Occurrences::Multiple(at) => {
-            let (nearest, overflow) = nearest_value(at, next.second(), 60);
-            next.replace_second(nearest)
-                .unwrap()
-                .add(Duration::minutes(if overflow { 1 } else { 0 }))
-        }
-    };
+    let mut wall_clock = to_wall_clock(after, timezone);
 
-    let set_seconds = next.second();
+    loop {
+        let next = get_upcoming_wall_clock_moment(wall_clock, at)?;
+        let moment = from_wall_clock(next, timezone);
 
-    let next = match &at.minutes {
-        Occurrences::First => {
-            if next.minute() == 0 {
-                next
-            } else {
-                next.replace_minute(0).unwrap().add(Duration::hours(1))
-            }
+        // Happens when the first occurrence of a repeated wall clock time already passed
+        if moment >= after {
+            return Ok(moment);
         }
-        Occurrences::Every => next, //.add(Duration::hours(1)),
-        Occurrences::Once(at) => {
-            if *at > next.minute() {
-                next.replace_minute(*at).unwrap()
-            } else {
-                next.replace_minute(*at).unwrap().add(Duration::hours(1))
-            }
-        }
-        Occurrences::Multiple(at) => {
-            let (nearest, overflow) = nearest_value(at, next.minute(), 60);
-            next.replace_minute(nearest)
-                .unwrap()
-                .add(Duration::hours(if overflow { 1 } else { 0 }))
-        }
-    };
 
-    // Required check for leap seconds
-    if next.second() != set_seconds {
-        return get_upcoming_moment(next, at);
+        wall_clock = next.add(Duration::seconds(1));
     }
+}
 
-    let set_minutes = next.minute();
+fn get_upcoming_wall_clock_moment(after: OffsetDateTime, at: &At) -> Result<OffsetDateTime> {
+    let after = second_precision(after);
 
-    let next = match &at.hours {
-        Occurrences::First => {
-            if next.hour() == 0 {
-                next
-            } else {
-                next.replace_hour(0).unwrap().add(Duration::days(1))
-            }
-        }
-        Occurrences::Every => next, //.add(Duration::days(1)),
-        Occurrences::Once(at) => {
-            if *at > next.hour() {
-                next.replace_hour(*at).unwrap()
+    let months = at.months.matched_values(1, 12);
+    let days = at.days.matched_values(1, 31);
+    let hours = at.hours.matched_values(0, 23);
+    let minutes = at.minutes.matched_values(0, 59);
+    let seconds = at.seconds.matched_values(0, 59);
+
+    let mut date = after.date();
+
+    while date.year() - after.year() <= SEARCH_YEARS {
+        if months.contains(&u8::from(date.month())) && matches_day(at, &days, date) {
+            let from = if date == after.date() {
+                after.time()
             } else {
-                next.replace_hour(*at).unwrap().add(Duration::days(1))
+                Time::MIDNIGHT
+            };
+
+            if let Some(time) = next_time(&hours, &minutes, &seconds, from) {
+                return Ok(date.with_time(time).assume_offset(after.offset()));
             }
         }
-        Occurrences::Multiple(at) => {
-            let (nearest, overflow) = nearest_value(at, next.hour(), days_in_current_month(next));
-            next.replace_hour(nearest)
-                .unwrap()
-                .add(Duration::days(if overflow { 1 } else { 0 }))
-        }
-    };
 
-    if next.second() != set_seconds || next.minute() != set_minutes {
-        return get_upcoming_moment(next, at);
+        date = date
+            .next_day()
+            .with_context(|| format!("Reached the maximum supported date for: {at}"))?;
     }
 
-    let set_hours = next.hour();
+    bail!("Failed to find a date matching the repetition pattern: {at}")
+}
 
-    let next = match &at.days {
-        Occurrences::First => {
-            if next.day() == 1 {
-                next
-            } else {
-                next_month(next.replace_day(1).unwrap())
-            }
-        }
-        Occurrences::Every => next, //.add(Duration::days(1)),
-        Occurrences::Once(at) => month_with_day(next, *at, *at <= next.day()),
-        Occurrences::Multiple(at) => {
-            let (nearest, overflow) = nearest_value(at, next.day(), days_in_current_month(next));
+/// Get the next moment matching a repetition pattern which doesn't fall into an exclusion window
+/// of the named calendars
+pub fn get_upcoming_allowed_moment(
+    after: OffsetDateTime,
+    at: &At,
+    timezone: &TimeZone,
+    calendars: &Calendars,
+    names: &[String],
+) -> Result<OffsetDateTime> {
+    skip_blackouts(
+        calendars,
+        names,
+        get_upcoming_moment(after, at, timezone)?,
+        |end| get_upcoming_moment(end, at, timezone),
+    )
+}
 
-            let mut next = month_with_day(next, nearest, true);
+fn matches_day(at: &At, days: &[u8], date: Date) -> bool {
+    let by_day = days.contains(&date.day());
+    let by_weekday = at.runs_on_weekday(date.weekday());
 
-            if overflow {
-                next = next_month(next);
-            }
+    if at.matches_any_day_field() {
+        by_day || by_weekday
+    } else {
+        by_day && by_weekday
+    }
+}
 
-            next
+/// Find the first time of day matching the provided values, starting from the provided one (included)
+fn next_time(hours: &[u8], minutes: &[u8], seconds: &[u8], from: Time) -> Option<Time> {
+    for &hour in hours.iter().filter(|hour| **hour >= from.hour()) {
+        let same_hour = hour == from.hour();
+
+        for &minute in minutes
+            .iter()
+            .filter(|minute| !same_hour || **minute >= from.minute())
+        {
+            let same_minute = same_hour && minute == from.minute();
+
+            if let Some(&second) = seconds
+                .iter()
+                .find(|second| !same_minute || **second >= from.second())
+            {
+                return Some(Time::from_hms(hour, minute, second).unwrap());
+            }
         }
+    }
+
+    None
+}
+
+/// Get the moment a task should run next, if it has a schedule
+///
+/// `last_planned` is the moment the previous run was planned for (if any), to avoid planning
+/// the same moment twice, while `last_run` is the latest entry in the task's history.
+///
+/// Occurrences of patterns falling into an exclusion window of the task's calendars are skipped,
+/// while other schedules are postponed to the end of the window.
+pub fn get_next_run(
+    after: OffsetDateTime,
+    task: &Task,
+    calendars: &Calendars,
+    last_planned: Option<OffsetDateTime>,
+    last_run: Option<&HistoryEntry>,
+) -> Result<Option<OffsetDateTime>> {
+    let Some(schedule) = &task.schedule else {
+        return Ok(None);
     };
 
-    if next.second() != set_seconds || next.minute() != set_minutes || next.hour() != set_hours {
-        return get_upcoming_moment(next, at);
-    }
+    match schedule {
+        Schedule::At(at) => {
+            let timezone = task.timezone()?;
 
-    let set_day = next.day();
+            let upcoming = match last_planned {
+                Some(last) => get_new_upcoming_moment(after, at, last, &timezone)?,
+                None => get_upcoming_moment(after, at, &timezone)?,
+            };
 
-    let next = match &at.months {
-        Occurrences::First => {
-            if next.month() == Month::January {
-                next
-            } else {
-                next_year(next).replace_month(Month::January).unwrap()
-            }
+            skip_blackouts(calendars, &task.calendars, upcoming, |end| {
+                get_upcoming_moment(end, at, &timezone)
+            })
+            .map(Some)
         }
-        Occurrences::Every => next,
-        Occurrences::Once(at) => {
-            let goal = Month::try_from(*at).unwrap();
 
-            if *at > next.month().into() {
-                let mut next = next;
+        Schedule::Interval(Interval { every, anchor }) => {
+            let from_run = last_run
+                .map(|last_run| match anchor {
+                    IntervalAnchor::Start => add_interval(last_run.started_at, *every),
+                    IntervalAnchor::End => add_interval(last_run.ended_at, *every),
+                })
+                .transpose()?;
 
-                'result: loop {
-                    for _ in 1..12 {
-                        if next.month() == goal {
-                            break 'result next;
-                        }
+            // Runs that failed to start don't have a history entry, so the interval is also
+            // counted from the moment the previous run was planned for
+            let from_planned = last_planned
+                .map(|planned| add_interval(planned, *every))
+                .transpose()?;
 
-                        next = next_month(next);
-                    }
-                }
-            } else {
-                let mut found = None;
+            let Some(upcoming) = from_run.max(from_planned) else {
+                // Never run yet, so there is no reason to wait
+                return Ok(Some(second_precision(after)));
+            };
 
-                for years in 0..4 {
-                    if let Ok(next) = next
-                        .replace_year(next.year() + years + 1)
-                        .and_then(|date| date.replace_month(goal))
-                    {
-                        found = Some(next);
-                        break;
-                    }
-                }
+            let upcoming = second_precision(upcoming.max(after));
 
-                found.with_context(|| {
-                    format!("Failed to determine a valid month/year couple for: {global_at}")
-                })?
-            }
+            skip_blackouts(calendars, &task.calendars, upcoming, Ok).map(Some)
         }
-        Occurrences::Multiple(at) => {
-            let nearest = nearest_values(at, next.month().into(), 12);
-
-            let mut nearest = nearest
-                .into_iter()
-                .map(|(_, month, overflow)| {
-                    (0..4)
-                        .filter_map(|years| {
-                            next.replace_year(next.year() + years + if overflow { 1 } else { 0 })
-                                .and_then(|next| {
-                                    next.replace_month(Month::try_from(month).unwrap())
-                                })
-                                .ok()
-                        })
-                        .collect::<Vec<_>>()
-                        .into_iter()
-                        .next()
-                        .with_context(|| {
-                            format!("Failed to determine a valid date for: {global_at}")
-                        })
-                })
-                .collect::<Result<Vec<_>>>()?;
 
-            nearest.sort_by_key(|moment| *moment - after);
+        Schedule::Once(OneShot { at, keep: _, done }) => {
+            // Failed runs are not attempted again
+            let ran = last_run.is_some_and(|last_run| last_run.started_at >= *at);
 
-            *nearest.first().unwrap()
+            if *done || ran {
+                return Ok(None);
+            }
+
+            skip_blackouts(calendars, &task.calendars, *at, Ok).map(Some)
         }
+    }
+}
+
+/// Add an interval (in seconds) to a moment, failing if the result is out of range
+pub fn add_interval(moment: OffsetDateTime, secs: u64) -> Result<OffsetDateTime> {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| moment.checked_add(Duration::seconds(secs)))
+        .context("Interval is too large")
+}
+
+/// Get the moments a task's repetition pattern matched between two moments (both excluded),
+/// up to a maximum number of moments, ignoring the ones falling into an exclusion window
+pub fn get_missed_runs(
+    task: &Task,
+    calendars: &Calendars,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    limit: usize,
+) -> Result<Vec<OffsetDateTime>> {
+    let Some(Schedule::At(at)) = &task.schedule else {
+        return Ok(vec![]);
     };
 
-    if next.second() != set_seconds
-        || next.minute() != set_minutes
-        || next.hour() != set_hours
-        || next.day() != set_day
-    {
-        return get_upcoming_moment(next, at);
+    let timezone = task.timezone()?;
+
+    let mut missed = vec![];
+    let mut after = second_precision(from).add(Duration::seconds(1));
+
+    while missed.len() < limit {
+        let moment = get_upcoming_moment(after, at, &timezone)?;
+
+        if moment >= to {
+            break;
+        }
+
+        match find_blackout(calendars, &task.calendars, moment)? {
+            Some(blackout) => after = blackout.ends_at,
+            None => {
+                missed.push(moment);
+                after = moment.add(Duration::seconds(1));
+            }
+        }
     }
 
-    Ok(second_precision(next))
+    Ok(missed)
 }
 
 pub fn get_new_upcoming_moment(
     after: OffsetDateTime,
     at: &At,
     last: OffsetDateTime,
+    timezone: &TimeZone,
 ) -> Result<OffsetDateTime> {
-    let upcoming = get_upcoming_moment(after, at)?;
+    let upcoming = get_upcoming_moment(after, at, timezone)?;
 
     if upcoming != last {
         Ok(upcoming)
     } else {
-        get_upcoming_moment(after.add(Duration::seconds(1)), at)
+        get_upcoming_moment(after.add(Duration::seconds(1)), at, timezone)
     }
 }
 
-fn nearest_value(candidates: &[u8], curr: u8, total: u8) -> (u8, bool) {
-    assert!(
-        !candidates.is_empty(),
-        "Candidates slice for the nearest value is empty!"
-    );
-
-    let (_, nearest, overflow) = candidates
-        .iter()
-        .map(|candidate| {
-            let (distance, overflow) = distance_from(*candidate, curr, total);
-            (distance, *candidate, overflow)
-        })
-        .min_by_key(|(distance, _, _)| *distance)
-        .unwrap();
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use time::{Date, Duration, Month, OffsetDateTime, Time};
+    use tz::TimeZone;
 
-    (nearest, overflow)
-}
+    use super::{
+        get_new_upcoming_moment, get_next_run, get_upcoming_allowed_moment, get_upcoming_moment,
+        get_upcoming_wall_clock_moment,
+    };
+    use crate::{
+        at::{At, Occurrences},
+        calendar::{Calendar, Calendars, DateRange, Window},
+        task::Task,
+    };
 
-fn nearest_values(candidates: &[u8], curr: u8, total: u8) -> Vec<(u8, u8, bool)> {
-    let mut nearest = candidates
-        .iter()
-        .map(|candidate| {
-            let (distance, overflow) = distance_from(*candidate, curr, total);
-            (distance, *candidate, overflow)
-        })
-        .collect::<Vec<_>>();
-
-    nearest.sort_by_key(|(distance, _, _)| *distance);
-    nearest
-}
+    /// Number of years the oracle looks into before giving up
+    const ORACLE_HORIZON_YEARS: i32 = 50;
+
+    /// Reference implementation, checking every second one after the other
+    /// (days that cannot match are skipped as a whole to keep it reasonably fast)
+    fn oracle(after: OffsetDateTime, at: &At) -> Option<OffsetDateTime> {
+        let months = oracle_table(&at.months, 1, 12);
+        let days = oracle_table(&at.days, 1, 31);
+        let hours = oracle_table(&at.hours, 0, 23);
+        let minutes = oracle_table(&at.minutes, 0, 59);
+        let seconds = oracle_table(&at.seconds, 0, 59);
+
+        let weekdays = match at.weekdays.expand(0, 6) {
+            None => vec![true; 7],
+            Some(values) => (0..7)
+                .map(|weekday| values.iter().any(|value| value % 7 == weekday))
+                .collect(),
+        };
+
+        // Classic cron only matches either day field when none of them starts with a wildcard
+        let any_day_field = at.cron.as_deref().is_some_and(|cron| {
+            let fields = cron.split_whitespace().collect::<Vec<_>>();
+            fields.len() == 5 && !fields[2].starts_with('*') && !fields[4].starts_with('*')
+        });
+
+        let matches_date = |date: Date| {
+            let by_day = days[usize::from(date.day())];
+            let by_weekday = weekdays[usize::from(date.weekday().number_days_from_sunday())];
+
+            months[usize::from(u8::from(date.month()))]
+                && if any_day_field {
+                    by_day || by_weekday
+                } else {
+                    by_day && by_weekday
+                }
+        };
 
-fn distance_from(candidate: u8, curr: u8, total: u8) -> (u8, bool) {
-    let overflow = candidate < curr;
+        let mut date = after.date();
+        let (hour, minute, second) = after.time().as_hms();
+        let mut from = u32::from(hour) * 3600 + u32::from(minute) * 60 + u32::from(second);
 
-    let distance = if overflow {
-        candidate + total - curr
-    } else {
-        candidate - curr
-    };
+        while date.year() <= after.year() + ORACLE_HORIZON_YEARS {
+            if matches_date(date) {
+                for secs in from..86_400 {
+                    let (hour, minute, second) = (secs / 3600, secs / 60 % 60, secs % 60);
 
-    (distance, overflow)
-}
+                    if hours[hour as usize] && minutes[minute as usize] && seconds[second as usize]
+                    {
+                        let time = Time::from_hms(hour as u8, minute as u8, second as u8);
+                        return Some(date.with_time(time.unwrap()).assume_utc());
+                    }
+                }
+            }
 
-fn days_in_current_month(from: OffsetDateTime) -> u8 {
-    let mut date = from;
-    let start_month = date.month();
+            date = date.next_day().unwrap();
+            from = 0;
+        }
 
-    let mut days = date.day();
+        None
+    }
 
-    while date.month() == start_month {
-        date = date.add(Duration::days(1));
-        days += 1;
+    /// Build a table indicating, for each value of a field, if it is matched
+    fn oracle_table(occ: &Occurrences, min: u8, max: u8) -> Vec<bool> {
+        (0..=max)
+            .map(|value| match occ {
+                Occurrences::First => value == min,
+                Occurrences::Every => value >= min,
+                _ => occ.expand(min, max).unwrap().contains(&value),
+            })
+            .collect()
     }
 
-    days - 1
-}
+    fn utc() -> TimeZone {
+        TimeZone::utc()
+    }
 
-fn next_month(from: OffsetDateTime) -> OffsetDateTime {
-    if from.month() == Month::December {
-        // Safe as december and january both have the same number of days
-        return next_year(from).replace_month(Month::January).unwrap();
+    fn moment(year: i32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> OffsetDateTime {
+        Date::from_calendar_date(year, Month::try_from(month).unwrap(), day)
+            .unwrap()
+            .with_hms(hour, minute, second)
+            .unwrap()
+            .assume_utc()
     }
 
-    // Accelerator for days that exist in all months
-    if from.day() < 28 {
-        return from.add(Duration::days(days_in_current_month(from).into()));
+    fn check_against_oracle(after: OffsetDateTime, at: &At) {
+        let expected = oracle(after, at);
+        let found = get_upcoming_moment(after, at, &utc());
+
+        match (expected, found) {
+            (Some(expected), Ok(found)) => assert_eq!(
+                found, expected,
+                "Invalid upcoming moment for '{at}' after {after}"
+            ),
+            (Some(expected), Err(err)) => {
+                panic!(
+                    "Failed to find upcoming moment {expected} for '{at}' after {after}: {err:?}"
+                )
+            }
+            (None, Ok(found)) => assert!(
+                found.year() > after.year() + ORACLE_HORIZON_YEARS,
+                "Found upcoming moment {found} for '{at}' after {after}, which never matches"
+            ),
+            (None, Err(_)) => {}
+        }
     }
 
-    month_with_day(from, from.day(), false)
-}
+    /// Start moments around month ends, in both leap and non-leap years
+    fn edge_moments() -> Vec<OffsetDateTime> {
+        let mut moments = vec![];
+
+        for year in [1999, 2000, 2023, 2024, 2099, 2100] {
+            for month in 1..=12 {
+                let first = moment(year, month, 1, 0, 0, 0);
+                let last = first.replace_day(1).unwrap() + Duration::days(31);
+                let last = last.replace_day(1).unwrap() - Duration::seconds(1);
+
+                moments.extend([
+                    first,
+                    first + Duration::seconds(1),
+                    moment(year, month, 15, 12, 34, 56),
+                    last.replace_time(Time::MIDNIGHT),
+                    last,
+                ]);
+            }
+        }
 
-fn month_with_day(from: OffsetDateTime, from_day: u8, try_current_month: bool) -> OffsetDateTime {
-    // Safe as all months have a '1' day
-    let mut next = if try_current_month {
-        from
-    } else {
-        next_month(from.replace_day(1).unwrap())
-    };
+        moments
+    }
 
-    while days_in_current_month(next) < from_day {
-        // Safe as all months have a '1' day
-        next = next_month(next.replace_day(1).unwrap());
+    #[test]
+    fn exhaustive_patterns_around_month_ends() {
+        let patterns = [
+            "s=*",
+            "m=*",
+            "h=0",
+            "h=23 m=59 s=59",
+            "m=*/7 s=30",
+            "h=9-17/4 m=15",
+            "D=1",
+            "D=15 h=12",
+            "D=28",
+            "D=29",
+            "D=30",
+            "D=31",
+            "D=1,31 h=23",
+            "D=1-31/10 h=12",
+            "M=1",
+            "M=2",
+            "M=2 D=28,29",
+            "M=2 D=29",
+            "M=2 D=29 h=12 m=30",
+            "M=1,3 D=31",
+            "M=12 D=31 h=23 m=59 s=59",
+            "M=1 D=1 h=0 m=0 s=0",
+            "M=*/3 D=1",
+            "M=10-12 D=31",
+            "W=mon",
+            "W=sun h=23 m=59",
+            "W=mon-fri h=9",
+            "M=3 W=mon",
+            "D=13 W=fri",
+            "M=2 D=29 W=sat",
+            "*/5 * * * *",
+            "0 0 13 * fri",
+            "0 0 29 2 *",
+            "0 0 */2 * 1",
+            "0 0 1-31 * mon",
+            "0 0 13 * */3",
+            "@weekly",
+            "@yearly",
+        ];
+
+        for pattern in patterns {
+            let at = At::parse(pattern).unwrap();
+
+            for after in edge_moments() {
+                check_against_oracle(after, &at);
+            }
+        }
     }
 
-    // Safe because we just checked that the current month had enough days for this
-    next.replace_day(from_day).unwrap()
-}
+    fn random_occurrences(rng: &mut StdRng, min: u8, max: u8) -> Occurrences {
+        match rng.gen_range(0..6) {
+            0 => Occurrences::First,
+            1 => Occurrences::Every,
+            2 => Occurrences::Once(rng.gen_range(min..=max)),
+            3 => Occurrences::Multiple(
+                (0..rng.gen_range(2..=4))
+                    .map(|_| rng.gen_range(min..=max))
+                    .collect(),
+            ),
+            4 => {
+                let from = rng.gen_range(min..=max);
+
+                Occurrences::Range {
+                    from,
+                    to: rng.gen_range(from..=max),
+                    step: rng.gen_range(1..=4),
+                }
+            }
+            _ => Occurrences::Step(rng.gen_range(1..=max / 2)),
+        }
+    }
 
-fn next_year(from: OffsetDateTime) -> OffsetDateTime {
-    let mut inc = 0;
+    fn random_at(rng: &mut StdRng) -> At {
+        let weekdays = match random_occurrences(rng, 0, 7) {
+            Occurrences::First => Occurrences::Every,
+            weekdays => weekdays,
+        };
+
+        At {
+            seconds: random_occurrences(rng, 0, 59),
+            minutes: random_occurrences(rng, 0, 59),
+            hours: random_occurrences(rng, 0, 23),
+            days: random_occurrences(rng, 1, 31),
+            months: random_occurrences(rng, 1, 12),
+            weekdays,
+            cron: rng.gen_bool(0.3).then(|| {
+                let field = |rng: &mut StdRng| if rng.gen_bool(0.7) { "1" } else { "*/2" };
+                format!("0 0 {} * {}", field(rng), field(rng))
+            }),
+        }
+    }
 
-    loop {
-        match from.replace_year(from.year() + inc) {
-            Ok(date) => return date,
-            Err(_) => {
-                inc += 1;
+    fn random_moment(rng: &mut StdRng) -> OffsetDateTime {
+        let date = Date::from_calendar_date(
+            rng.gen_range(1990..=2110),
+            Month::try_from(rng.gen_range(1..=12)).unwrap(),
+            1,
+        )
+        .unwrap();
+
+        let date = date + Duration::days(rng.gen_range(0..31));
+
+        date.with_hms(
+            rng.gen_range(0..24),
+            rng.gen_range(0..60),
+            rng.gen_range(0..60),
+        )
+        .unwrap()
+        .assume_utc()
+    }
+
+    #[test]
+    fn random_patterns_match_oracle() {
+        let mut rng = StdRng::seed_from_u64(0xC2_04_1E);
+
+        for _ in 0..3000 {
+            let at = random_at(&mut rng);
+            let after = random_moment(&mut rng);
+
+            check_against_oracle(after, &at);
+        }
+    }
+
+    #[test]
+    fn date_matching_agrees_with_search() {
+        let mut rng = StdRng::seed_from_u64(0xDA_7E_50);
+
+        // The search covers a full cycle of the gregorian calendar
+        let reference = moment(2000, 1, 1, 0, 0, 0);
+
+        // Patterns conflicting with each other are rejected when parsing them
+        let at = |days, months, weekdays, cron: Option<&str>| At {
+            days: Occurrences::Multiple(days),
+            months: Occurrences::Multiple(months),
+            weekdays: Occurrences::Multiple(weekdays),
+            cron: cron.map(str::to_owned),
+            ..At::parse("h=0 m=0 s=0").unwrap()
+        };
+
+        let mut patterns = vec![
+            at(vec![30], vec![2], vec![0, 1, 2, 3, 4, 5, 6], None),
+            at(vec![31], vec![4, 6], vec![0, 1, 2, 3, 4, 5, 6], None),
+            at(vec![29], vec![2], vec![1], None),
+            at(vec![31], vec![2], vec![5], None),
+            at(vec![31], vec![2], vec![1], Some("0 0 31 2 1")),
+        ];
+
+        patterns.extend((0..3000).map(|_| random_at(&mut rng)));
+
+        for at in patterns {
+            assert_eq!(
+                at.matches_any_date(),
+                get_upcoming_wall_clock_moment(reference, &at).is_ok(),
+                "{}",
+                at.describe()
+            );
+        }
+    }
+
+    #[test]
+    fn successive_moments_match_oracle() {
+        let mut rng = StdRng::seed_from_u64(0x5E_C0_4D);
+
+        for _ in 0..300 {
+            let at = random_at(&mut rng);
+            let mut after = random_moment(&mut rng);
+
+            for _ in 0..5 {
+                let Some(expected) = oracle(after, &at) else {
+                    break;
+                };
+
+                let found = get_new_upcoming_moment(after, &at, after, &utc()).unwrap();
+
+                let expected = if expected == after {
+                    oracle(after + Duration::seconds(1), &at).unwrap()
+                } else {
+                    expected
+                };
+
+                assert_eq!(
+                    found, expected,
+                    "Invalid successive moment for '{at}' after {after}"
+                );
+
+                after = found;
             }
         }
     }
+
+    #[test]
+    fn cron_wildcard_day_fields() {
+        // Stepped wildcards don't restrict the day fields, so both of them must match
+        let at = At::parse("0 0 */2 * 1").unwrap();
+        let mut after = moment(2026, 11, 1, 0, 0, 0);
+
+        for expected in [
+            moment(2026, 11, 9, 0, 0, 0),
+            moment(2026, 11, 23, 0, 0, 0),
+            moment(2026, 12, 7, 0, 0, 0),
+        ] {
+            check_against_oracle(after, &at);
+
+            let found = get_upcoming_moment(after, &at, &utc()).unwrap();
+            assert_eq!(found, expected);
+
+            after = found + Duration::seconds(1);
+        }
+    }
+
+    #[test]
+    fn interval_runs_failing_to_start() {
+        let task: Task = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "failing",
+            "cmd": "false",
+            "schedule": { "every": 3600, "anchor": "End" },
+        }))
+        .unwrap();
+
+        let planned = moment(2026, 11, 1, 12, 0, 0);
+        let after = moment(2026, 11, 1, 12, 0, 5);
+        let next = get_next_run(after, &task, &Calendars::new(), Some(planned), None).unwrap();
+
+        // The run didn't write a history entry, but must not start again right away
+        assert_eq!(next, Some(moment(2026, 11, 1, 13, 0, 0)));
+
+        // Never planned nor run yet
+        let next = get_next_run(after, &task, &Calendars::new(), None, None).unwrap();
+        assert_eq!(next, Some(after));
+    }
+
+    #[test]
+    fn huge_intervals() {
+        let task: Task = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "huge",
+            "cmd": "true",
+            "schedule": { "every": 86_400_000_000_000u64, "anchor": "End" },
+        }))
+        .unwrap();
+
+        let planned = moment(2026, 11, 1, 12, 0, 0);
+        let next = get_next_run(planned, &task, &Calendars::new(), Some(planned), None);
+
+        assert!(next.is_err());
+    }
+
+    #[test]
+    fn dst_transitions() {
+        let paris = TimeZone::from_posix_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let at = At::parse("h=2 m=30").unwrap();
+
+        // Skipped wall clock time, run at 03:30 CEST
+        assert_eq!(
+            get_upcoming_moment(moment(2026, 3, 28, 12, 0, 0), &at, &paris).unwrap(),
+            moment(2026, 3, 29, 1, 30, 0)
+        );
+
+        // Repeated wall clock time, only run at 02:30 CEST and not at 02:30 CET
+        let first = get_upcoming_moment(moment(2026, 10, 24, 12, 0, 0), &at, &paris).unwrap();
+        assert_eq!(first, moment(2026, 10, 25, 0, 30, 0));
+
+        assert_eq!(
+            get_new_upcoming_moment(first, &at, first, &paris).unwrap(),
+            moment(2026, 10, 26, 1, 30, 0)
+        );
+    }
+
+    #[test]
+    fn exclusion_windows() {
+        let at = At::parse("h=9").unwrap();
+
+        let window = |from, to| {
+            Window::Range(DateRange {
+                from,
+                to,
+                label: None,
+            })
+        };
+
+        let calendars = Calendars::from([(
+            "holidays".to_owned(),
+            Calendar {
+                name: "holidays".to_owned(),
+                windows: vec![
+                    window(moment(2026, 12, 24, 0, 0, 0), moment(2026, 12, 26, 0, 0, 0)),
+                    // Ends right when an occurrence starts
+                    window(
+                        moment(2026, 12, 26, 12, 0, 0),
+                        moment(2026, 12, 27, 9, 0, 0),
+                    ),
+                ],
+            },
+        )]);
+
+        let names = ["holidays".to_owned()];
+        let next = |after| get_upcoming_allowed_moment(after, &at, &utc(), &calendars, &names);
+
+        assert_eq!(
+            next(moment(2026, 12, 23, 12, 0, 0)).unwrap(),
+            moment(2026, 12, 26, 9, 0, 0)
+        );
+
+        assert_eq!(
+            next(moment(2026, 12, 26, 10, 0, 0)).unwrap(),
+            moment(2026, 12, 27, 9, 0, 0)
+        );
+
+        // Unknown calendars don't exclude anything
+        assert_eq!(
+            get_upcoming_allowed_moment(
+                moment(2026, 12, 23, 12, 0, 0),
+                &at,
+                &utc(),
+                &calendars,
+                &["unknown".to_owned()]
+            )
+            .unwrap(),
+            moment(2026, 12, 24, 9, 0, 0)
+        );
+    }
 }
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use nix::{
+    errno::Errno,
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+};
+use regex::Regex;
+
+use crate::{
+    error_anyhow,
+    glob::compile_glob,
+    task::{PathWatch, Task, Tasks},
+};
+
+/// Interval at which changes are looked for
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Watches the paths of tasks with a watch trigger, until dropped
+pub struct PathWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl PathWatcher {
+    /// Start watching the paths of the provided tasks in a separate thread
+    ///
+    /// Once a task's paths stopped changing for its debounce delay, `on_change` is called
+    /// with the first path that changed.
+    pub fn start(tasks: &Tasks, on_change: impl Fn(&Task, PathBuf) + Send + 'static) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let watched = tasks
+            .values()
+            .filter(|task| task.watch.is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !watched.is_empty() {
+            match Watches::new(watched) {
+                Ok(watches) => {
+                    let stop = Arc::clone(&stop);
+                    std::thread::spawn(move || watches.run(&stop, on_change));
+                }
+                Err(err) => error_anyhow!(
+                    err.context("Failed to watch paths, tasks watching them will not run")
+                ),
+            }
+        }
+
+        Self { stop }
+    }
+}
+
+impl Drop for PathWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Watches {
+    inotify: Inotify,
+    tasks: Vec<WatchingTask>,
+
+    /// Tasks watching each path, along with the watched path
+    descriptors: HashMap<WatchDescriptor, Vec<(usize, PathBuf)>>,
+}
+
+struct WatchingTask {
+    task: Task,
+    globs: Vec<Regex>,
+    debounce: Duration,
+}
+
+impl Watches {
+    fn new(tasks: Vec<Task>) -> Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .context("Failed to initialize inotify")?;
+
+        let mut watching = vec![];
+        let mut descriptors = HashMap::<_, Vec<_>>::new();
+
+        for task in tasks {
+            let PathWatch {
+                paths,
+                globs,
+                debounce,
+            } = task.watch.clone().unwrap();
+
+            let globs = match globs.iter().map(|glob| compile_glob(glob)).collect() {
+                Ok(globs) => globs,
+                Err(err) => {
+                    error_anyhow!(
+                        err.context(format!("Failed to watch paths of task '{}'", task.name))
+                    );
+                    continue;
+                }
+            };
+
+            for path in paths {
+                // Only files being created, written or moved into a directory are considered as changes
+                let flags = AddWatchFlags::IN_CREATE
+                    | AddWatchFlags::IN_MODIFY
+                    | AddWatchFlags::IN_CLOSE_WRITE
+                    | AddWatchFlags::IN_MOVED_TO;
+
+                match inotify.add_watch(&path, flags) {
+                    Ok(descriptor) => descriptors
+                        .entry(descriptor)
+                        .or_default()
+                        .push((watching.len(), path)),
+                    Err(err) => error_anyhow!(anyhow::Error::from(err).context(format!(
+                        "Failed to watch path '{}' of task '{}'",
+                        path.display(),
+                        task.name
+                    ))),
+                }
+            }
+
+            watching.push(WatchingTask {
+                task,
+                globs,
+                debounce: Duration::from_secs(debounce),
+            });
+        }
+
+        Ok(Self {
+            inotify,
+            tasks: watching,
+            descriptors,
+        })
+    }
+
+    fn run(self, stop: &AtomicBool, on_change: impl Fn(&Task, PathBuf)) {
+        // First changed path and moment of the last change, for each task
+        let mut changes = HashMap::<usize, (PathBuf, Instant)>::new();
+
+        while !stop.load(Ordering::Relaxed) {
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => vec![],
+                Err(err) => {
+                    error_anyhow!(
+                        anyhow::Error::from(err).context("Failed to read changes in watched paths")
+                    );
+                    vec![]
+                }
+            };
+
+            for event in events {
+                let Some(watchers) = self.descriptors.get(&event.wd) else {
+                    continue;
+                };
+
+                for (index, watched) in watchers {
+                    // Events on watched files don't come with a name
+                    let path = match &event.name {
+                        Some(name) => watched.join(name),
+                        None => watched.clone(),
+                    };
+
+                    let WatchingTask { globs, .. } = &self.tasks[*index];
+
+                    let matches = globs.is_empty()
+                        || path
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .is_some_and(|name| globs.iter().any(|glob| glob.is_match(name)));
+
+                    if matches {
+                        changes
+                            .entry(*index)
+                            .and_modify(|(_, last)| *last = Instant::now())
+                            .or_insert((path, Instant::now()));
+                    }
+                }
+            }
+
+            changes.retain(|index, (path, last)| {
+                let WatchingTask { task, debounce, .. } = &self.tasks[*index];
+
+                if last.elapsed() < *debounce {
+                    return true;
+                }
+
+                on_change(task, path.clone());
+                false
+            });
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
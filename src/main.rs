@@ -1,4 +1,4 @@
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 #![forbid(unused_must_use)]
 
 mod cmd;
@@ -18,19 +18,35 @@ use std::{fs, sync::atomic::Ordering};
 use anyhow::{bail, Context, Result};
 use clap::Parser;
 use colored::Colorize;
+use nix::unistd::{geteuid, Uid};
 use rand::random;
 use tabular::{row, Table};
 
 use crate::{
     at::At,
-    cmd::{Action, Cmd, HistoryArgs, LogsArgs, RegisterArgs, RunArgs, UnregisterArgs},
+    calendar::Calendar,
+    cmd::{
+        Action, CalendarAction, CalendarArgs, CalendarImportArgs, CalendarRemoveArgs, Cmd,
+        HistoryArgs, LogsArgs, NextArgs, PauseArgs, RegisterArgs, ResumeArgs, RunArgs,
+        UnregisterArgs,
+    },
     daemon::{is_daemon_running, start_daemon, DaemonClient, RunningTask},
-    datetime::get_now,
-    history::History,
+    datetime::{get_now, in_timezone, load_timezone, local_timezone, parse_datetime},
+    env::parse_env_file,
+    glob::compile_glob,
+    history::{History, RunTrigger},
+    ics::parse_ics,
+    limits::ResourceLimits,
     paging::run_pager,
-    save::{construct_data_dir_paths, read_history_file, read_tasks, write_tasks},
+    paths::Paths,
+    save::{
+        construct_data_dir_paths, lock_tasks, read_calendars, read_history_file, read_tasks,
+        write_calendars, write_tasks,
+    },
+    schedule::{Interval, OneShot, Schedule},
     sleep::sleep_ms,
-    task::Task,
+    task::{PathWatch, RetryPolicy, Task, TaskCommand},
+    users::{ensure_can_switch_to, ensure_user_can_access, find_group, find_user},
 };
 
 fn main() -> ! {
@@ -57,6 +73,16 @@ fn inner_main() -> Result<()> {
 
     let paths = construct_data_dir_paths(cmd.data_dir)?;
 
+    // Prevent the daemon and other commands from modifying the tasks at the same time
+    let _tasks_lock = match cmd.action {
+        Action::Register(_)
+        | Action::Unregister(_)
+        | Action::Pause(_)
+        | Action::Resume(_)
+        | Action::Calendar(_) => Some(lock_tasks(&paths)?),
+        _ => None,
+    };
+
     let mut tasks = read_tasks(&paths)?;
 
     match cmd.action {
@@ -71,7 +97,14 @@ fn inner_main() -> Result<()> {
 
             let history = read_history_file(&paths.history_file)?.unwrap_or_else(History::empty);
 
-            let mut table = Table::new("{:>} {:<} {:<} {:<} {:<} {:<}");
+            // Resource limits are only shown in verbose mode
+            let mut table = Table::new(if cmd.verbose {
+                "{:>} {:<} {:<} {:<} {:<} {:<} {:<} {:<}"
+            } else {
+                "{:>} {:<} {:<} {:<} {:<} {:<} {:<}"
+            });
+
+            let now = get_now();
 
             for task in tasks.values() {
                 let last_run = match history.for_task(task.id).last() {
@@ -87,17 +120,47 @@ fn inner_main() -> Result<()> {
                     }
                 };
 
-                table.add_row(row!(
+                let name = if !task.is_paused_at(now) {
+                    task.name.bright_yellow()
+                } else {
+                    match task.paused_until {
+                        Some(until) => format!(
+                            "{} (paused until {})",
+                            task.name,
+                            until.replace_nanosecond(0).unwrap()
+                        ),
+                        None => format!("{} (paused)", task.name),
+                    }
+                    .bright_black()
+                };
+
+                let row = row!(
                     "*".bright_blue(),
-                    task.name.bright_yellow(),
+                    name,
                     last_run,
-                    match &task.shell {
-                        Some(shell) => shell.bright_magenta(),
-                        None => "-".bright_black(),
+                    match (&task.cmd, &task.shell) {
+                        (TaskCommand::Exec(_), _) => "(exec)".bright_black(),
+                        (TaskCommand::Shell(_), Some(shell)) =>
+                            shell_words::join(shell).bright_magenta(),
+                        (TaskCommand::Shell(_), None) => "-".bright_black(),
                     },
-                    task.cmd.bright_cyan(),
-                    task.at.encode().bright_black(),
-                ));
+                    task.cmd.to_string().bright_cyan(),
+                    task.describe().bright_blue(),
+                    match (&task.schedule, &task.timezone) {
+                        (Some(schedule), Some(timezone)) => format!("{schedule} ({timezone})"),
+                        (Some(schedule), None) => schedule.to_string(),
+                        (None, _) => "-".to_owned(),
+                    }
+                    .bright_black(),
+                );
+
+                table.add_row(if !cmd.verbose {
+                    row
+                } else if task.limits.is_empty() {
+                    row.with_cell("-".bright_black())
+                } else {
+                    row.with_cell(task.limits.to_string().bright_red())
+                });
             }
 
             println!("{}", table);
@@ -125,37 +188,264 @@ fn inner_main() -> Result<()> {
             }
         }
 
-        Action::Register(RegisterArgs {
-            name,
-            at,
-            using,
-            run,
-            force_override,
-            ignore_identical,
-            silent,
-        }) => {
+        Action::Register(args) => {
+            let RegisterArgs {
+                name,
+                at,
+                every,
+                once,
+                keep,
+                anchor,
+                timezone,
+                using,
+                exec,
+                cwd,
+                env,
+                env_file,
+                user,
+                group,
+                nice,
+                ionice,
+                max_cpu_time,
+                max_memory,
+                max_open_files,
+                max_processes,
+                overlap,
+                slots,
+                timeout,
+                max_attempts,
+                retry_delay,
+                retry_backoff,
+                retry_on,
+                catch_up,
+                jitter,
+                after,
+                on_failure,
+                watch,
+                watch_glob,
+                debounce,
+                on_startup,
+                startup_delay,
+                calendar,
+                run,
+                argv,
+                force_override,
+                ignore_identical,
+                silent,
+            } = *args;
             if !Task::is_valid_name(&name) {
                 bail!("The provided name is invalid, only letters, digits, dashes and underscores are allowed.");
             }
 
-            let parsed_at = At::parse(&at)?;
+            if let Some(slot) = slots.iter().find(|slot| !Task::is_valid_name(slot)) {
+                bail!("Slot name '{slot}' is invalid, only letters, digits, dashes and underscores are allowed.");
+            }
+
+            let schedule = match (at, every, once) {
+                (Some(at), None, None) => Some(Schedule::At(At::parse(&at)?)),
+                (None, Some(every), None) => Some(Schedule::Interval(Interval { every, anchor })),
+                (None, None, Some(once)) => {
+                    let timezone = match &timezone {
+                        Some(timezone) => load_timezone(timezone)
+                            .context("Failed to load the provided time zone")?,
+                        None => local_timezone(),
+                    };
+
+                    let at = parse_datetime(&once, &timezone)?;
+
+                    if at <= get_now() {
+                        bail!("The provided date is in the past.");
+                    }
+
+                    Some(Schedule::Once(OneShot {
+                        at,
+                        keep,
+                        done: false,
+                    }))
+                }
+                (None, None, None) => None,
+                _ => unreachable!(),
+            };
+
+            for glob in &watch_glob {
+                compile_glob(glob)?;
+            }
+
+            // The daemon may not run from the same directory
+            let watch = if watch.is_empty() {
+                None
+            } else {
+                let paths = watch
+                    .iter()
+                    .map(|path| {
+                        fs::canonicalize(path).with_context(|| {
+                            format!("Failed to find watched path: {}", path.display())
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+
+                Some(PathWatch {
+                    paths,
+                    globs: watch_glob,
+                    debounce,
+                })
+            };
+
+            if let Some((var, _)) = env.iter().find(|(var, _)| var.starts_with("CRONY_")) {
+                bail!("Variable '{var}' cannot be set as variables starting with 'CRONY_' are reserved.");
+            }
+
+            let cwd = cwd
+                .map(|cwd| {
+                    fs::canonicalize(&cwd).with_context(|| {
+                        format!("Failed to find working directory: {}", cwd.display())
+                    })
+                })
+                .transpose()?;
+
+            if let Some(cwd) = cwd.as_ref().filter(|cwd| !cwd.is_dir()) {
+                bail!("Working directory is not a directory: {}", cwd.display());
+            }
+
+            // Ensure the env file is valid, while it is read again before each run
+            let env_file = env_file
+                .map(|env_file| {
+                    let content = fs::read_to_string(&env_file).with_context(|| {
+                        format!("Failed to read the env file at: {}", env_file.display())
+                    })?;
+
+                    parse_env_file(&content)?;
+
+                    fs::canonicalize(&env_file)
+                        .context("Failed to canonicalize the env file's path")
+                })
+                .transpose()?;
+
+            let shell = using
+                .map(|using| {
+                    let shell = shell_words::split(&using)
+                        .with_context(|| format!("Invalid shell command: {using}"))?;
+
+                    if shell.is_empty() {
+                        bail!("The shell command cannot be empty.");
+                    }
+
+                    Ok(shell)
+                })
+                .transpose()?;
+
+            let cmd = match run {
+                Some(run) if exec => {
+                    let argv = shell_words::split(&run)
+                        .with_context(|| format!("Invalid command: {run}"))?;
+
+                    if argv.is_empty() {
+                        bail!("The command cannot be empty.");
+                    }
+
+                    TaskCommand::Exec(argv)
+                }
+                Some(run) => TaskCommand::Shell(run),
+                // Arguments provided after '--' require '--exec'
+                None => TaskCommand::Exec(argv),
+            };
 
             let task = Task {
                 id: random(),
                 name: name.clone(),
-                at: parsed_at,
-                cmd: run,
-                shell: using,
+                enabled: true,
+                paused_until: None,
+                paused_at: None,
+                past_pauses: vec![],
+                schedule,
+                cmd,
+                shell,
+                cwd,
+                env: env.into_iter().collect(),
+                env_file,
+                user,
+                group,
+                limits: ResourceLimits {
+                    nice,
+                    ionice,
+                    cpu_time: max_cpu_time,
+                    memory: max_memory,
+                    open_files: max_open_files,
+                    processes: max_processes,
+                },
+                timezone,
+                overlap,
+                slots,
+                timeout,
+                retry: max_attempts.map(|max_attempts| RetryPolicy {
+                    max_attempts,
+                    delay: retry_delay,
+                    backoff: retry_backoff,
+                    on_codes: retry_on,
+                }),
+                catch_up: Some(catch_up),
+                jitter,
+                after,
+                on_failure,
+                watch,
+                on_startup: on_startup.then_some(startup_delay),
+                calendars: calendar,
             };
 
-            let next = task.at.next_occurrence().with_context(|| {
-                format!("Failed to find a valid next occurrence for repetition pattern: {at}",)
-            })?;
+            task.timezone()
+                .context("Failed to load the provided time zone")?;
+
+            ensure_can_run_as(&paths, task.user.as_deref(), task.group.as_deref())?;
+
+            if (task.user.is_some() || task.group.is_some()) && task.limits.require_privileges() {
+                bail!("Negative niceness values and the realtime I/O class cannot be used by tasks running as another user or group, as limits are applied once privileges are dropped.");
+            }
+
+            if let Some(user) = &task.user {
+                let user = find_user(user)?;
+
+                let gid = match &task.group {
+                    Some(group) => find_group(group)?.gid,
+                    None => user.gid,
+                };
+
+                ensure_user_can_access(
+                    user.uid,
+                    gid,
+                    task.cwd.as_deref(),
+                    task.env_file.as_deref(),
+                )?;
+            }
+
+            let calendars = read_calendars(&paths)?;
+
+            if let Some(calendar) = task
+                .calendars
+                .iter()
+                .find(|calendar| !calendars.contains_key(*calendar))
+            {
+                bail!("Calendar '{}' does not exist.", calendar.bright_yellow());
+            }
+
+            // Only tasks with a schedule can fail to be planned
+            let next =
+                get_next_run(get_now(), &task, &calendars, None, None).with_context(|| {
+                    format!(
+                        "Failed to find a valid next occurrence for schedule: {}",
+                        task.schedule.as_ref().unwrap()
+                    )
+                })?;
 
             if let Some(existing) = tasks.get(&name) {
                 let mut simili = existing.clone();
                 simili.id = task.id;
 
+                // Pausing a task doesn't change its parameters
+                simili.enabled = task.enabled;
+                simili.paused_until = task.paused_until;
+                simili.paused_at = task.paused_at;
+                simili.past_pauses = task.past_pauses.clone();
+
                 let identical = simili == task;
 
                 if identical && ignore_identical {
@@ -167,6 +457,22 @@ fn inner_main() -> Result<()> {
                 }
             }
 
+            if let Some(upstream) = task
+                .upstreams()
+                .find(|upstream| **upstream != name && !tasks.contains_key(*upstream))
+            {
+                bail!("Task '{}' does not exist.", upstream.bright_yellow());
+            }
+
+            if let Some(cycle) = task.find_dependency_cycle(&tasks) {
+                bail!(
+                    "This task would create a dependency cycle: {} (each task running after the next one)",
+                    cycle.join(" -> ").bright_yellow()
+                );
+            }
+
+            let description = task.describe();
+
             fs::write(
                 paths.task_log_file(&name),
                 format!("-- Task created at {} --", get_now()),
@@ -178,30 +484,36 @@ fn inner_main() -> Result<()> {
             write_tasks(&paths, &tasks)?;
 
             if !silent {
-                success!("Successfully registered task {}.", name.bright_yellow());
                 success!(
-                    "If the daemon is running, the task will run on {}",
-                    next.to_string().bright_magenta()
+                    "Successfully registered task {}, which will run {}.",
+                    name.bright_yellow(),
+                    description.bright_blue()
                 );
-            }
 
-            let socket_file = &paths.daemon_socket_file;
-
-            if is_daemon_running(socket_file)? {
-                debug!("Asking the daemon to reload the tasks...");
-
-                let mut client = DaemonClient::connect(socket_file)?;
-                client.reload_tasks()?;
-
-                success!("Daemon successfully reloaded the tasks!");
-            } else {
-                warn!("Warning: the daemon is not running.")
+                if let Some(next) = next {
+                    success!(
+                        "If the daemon is running, the task will run on {}",
+                        next.to_string().bright_magenta()
+                    );
+                }
             }
+
+            reload_daemon_tasks(&paths)?;
         }
 
         Action::Unregister(UnregisterArgs { name }) => {
-            if !tasks.contains_key(&name) {
+            let Some(task) = tasks.get(&name) else {
                 bail!("Task '{}' does not exist.", name.bright_yellow());
+            };
+
+            let dependents = task.dependents(&tasks);
+
+            if !dependents.is_empty() {
+                bail!(
+                    "Task '{}' is still a dependency of tasks: {}",
+                    name.bright_yellow(),
+                    dependents.join(", ").bright_yellow()
+                );
             }
 
             fs::remove_file(paths.task_log_file(&name))
@@ -213,29 +525,309 @@ fn inner_main() -> Result<()> {
 
             success!("Successfully removed task {}.", name.bright_yellow());
 
-            let socket_file = &paths.daemon_socket_file;
+            reload_daemon_tasks(&paths)?;
+        }
+
+        Action::Pause(PauseArgs { name, until }) => {
+            let task = tasks
+                .get_mut(&name)
+                .with_context(|| format!("Task '{}' does not exist.", name.bright_yellow()))?;
+
+            let until = until
+                .map(|until| parse_datetime(&until, &local_timezone()))
+                .transpose()?;
 
-            if is_daemon_running(socket_file)? {
-                debug!("Asking the daemon to reload the tasks...");
+            let now = get_now();
 
-                let mut client = DaemonClient::connect(socket_file)?;
-                client.reload_tasks()?;
+            if until.is_some_and(|until| until <= now) {
+                bail!("The provided date is in the past.");
+            }
 
-                success!("Daemon successfully reloaded the tasks!");
-            } else {
-                warn!("Warning: the daemon is not running.")
+            task.pause(now, until);
+            forget_past_pauses(&paths, task)?;
+
+            write_tasks(&paths, &tasks)?;
+
+            match until {
+                Some(until) => success!(
+                    "Successfully paused task {} until {}.",
+                    name.bright_yellow(),
+                    until.to_string().bright_magenta()
+                ),
+                None => success!("Successfully paused task {}.", name.bright_yellow()),
+            }
+
+            reload_daemon_tasks(&paths)?;
+        }
+
+        Action::Resume(ResumeArgs { name }) => {
+            let task = tasks
+                .get_mut(&name)
+                .with_context(|| format!("Task '{}' does not exist.", name.bright_yellow()))?;
+
+            let now = get_now();
+
+            if !task.is_paused_at(now) {
+                bail!("Task '{}' is not paused.", name.bright_yellow());
+            }
+
+            task.resume(now);
+            forget_past_pauses(&paths, task)?;
+
+            write_tasks(&paths, &tasks)?;
+
+            success!("Successfully resumed task {}.", name.bright_yellow());
+
+            reload_daemon_tasks(&paths)?;
+        }
+
+        Action::Calendar(CalendarArgs { action }) => {
+            let mut calendars = read_calendars(&paths)?;
+
+            match action {
+                CalendarAction::List => {
+                    if calendars.is_empty() {
+                        info!("No calendar found.");
+                        return Ok(());
+                    }
+
+                    for calendar in calendars.values() {
+                        info!(
+                            "Calendar {} ({} exclusion windows):",
+                            calendar.name.bright_yellow(),
+                            calendar.windows.len()
+                        );
+
+                        let mut table = Table::new("{:>} {:<} {:<}");
+
+                        for window in &calendar.windows {
+                            table.add_row(row!(
+                                "*".bright_blue(),
+                                window.to_string().bright_magenta(),
+                                window.label().unwrap_or("-").bright_black(),
+                            ));
+                        }
+
+                        println!("{table}");
+                    }
+                }
+
+                CalendarAction::Import(CalendarImportArgs {
+                    name,
+                    file,
+                    timezone,
+                    force_override,
+                }) => {
+                    if !Task::is_valid_name(&name) {
+                        bail!("The provided name is invalid, only letters, digits, dashes and underscores are allowed.");
+                    }
+
+                    if calendars.contains_key(&name) && !force_override {
+                        bail!("A calendar with this name already exists!");
+                    }
+
+                    if let Some(timezone) = &timezone {
+                        load_timezone(timezone).context("Failed to load the provided time zone")?;
+                    }
+
+                    let content = fs::read_to_string(&file).with_context(|| {
+                        format!("Failed to read the calendar file at: {}", file.display())
+                    })?;
+
+                    let calendar = if content.trim_start().starts_with("BEGIN:VCALENDAR") {
+                        Calendar {
+                            name: name.clone(),
+                            windows: parse_ics(&content, timezone.as_deref())?,
+                        }
+                    } else {
+                        Calendar::parse_simple(name.clone(), &content, timezone.as_deref())?
+                    };
+
+                    let windows = calendar.windows.len();
+
+                    calendars.insert(name.clone(), calendar);
+
+                    write_calendars(&paths, &calendars)?;
+
+                    success!(
+                        "Successfully imported calendar {} with {} exclusion window(s).",
+                        name.bright_yellow(),
+                        windows.to_string().bright_yellow()
+                    );
+
+                    reload_daemon_tasks(&paths)?;
+                }
+
+                CalendarAction::Remove(CalendarRemoveArgs { name }) => {
+                    if !calendars.contains_key(&name) {
+                        bail!("Calendar '{}' does not exist.", name.bright_yellow());
+                    }
+
+                    let users = tasks
+                        .values()
+                        .filter(|task| task.calendars.contains(&name))
+                        .map(|task| task.name.as_str())
+                        .collect::<Vec<_>>();
+
+                    if !users.is_empty() {
+                        bail!(
+                            "Calendar '{}' is still used by tasks: {}",
+                            name.bright_yellow(),
+                            users.join(", ").bright_yellow()
+                        );
+                    }
+
+                    calendars.remove(&name);
+
+                    write_calendars(&paths, &calendars)?;
+
+                    success!("Successfully removed calendar {}.", name.bright_yellow());
+
+                    reload_daemon_tasks(&paths)?;
+                }
             }
         }
 
+        Action::Next(NextArgs {
+            name,
+            at,
+            timezone,
+            calendar,
+            count,
+            from,
+        }) => {
+            let calendars = read_calendars(&paths)?;
+
+            let (at, timezone, jitter, excluded) = match (name, at) {
+                (Some(name), None) => {
+                    let task = tasks.get(&name).with_context(|| {
+                        format!("Task '{}' does not exist.", name.bright_yellow())
+                    })?;
+
+                    let Some(Schedule::At(at)) = &task.schedule else {
+                        bail!("Task '{}' doesn't run on a repetition pattern, so its occurrences cannot be predicted.", name.bright_yellow());
+                    };
+
+                    (
+                        at.clone(),
+                        task.timezone()?,
+                        jitter_offset(task),
+                        task.calendars.clone(),
+                    )
+                }
+                (None, Some(at)) => {
+                    let timezone = match timezone {
+                        Some(timezone) => load_timezone(&timezone)
+                            .context("Failed to load the provided time zone")?,
+                        None => local_timezone(),
+                    };
+
+                    if let Some(calendar) = calendar
+                        .iter()
+                        .find(|calendar| !calendars.contains_key(*calendar))
+                    {
+                        bail!("Calendar '{}' does not exist.", calendar.bright_yellow());
+                    }
+
+                    (At::parse(&at)?, timezone, time::Duration::ZERO, calendar)
+                }
+                _ => unreachable!(),
+            };
+
+            let from = match from {
+                Some(from) => parse_datetime(&from, &timezone)?,
+                None => get_now(),
+            };
+
+            let mut table = Table::new("{:>} {:<} {:<}");
+            let mut last = None;
+
+            for _ in 0..count {
+                let after = match last {
+                    Some(last) => last + time::Duration::seconds(1),
+                    None => from - jitter,
+                };
+
+                let next =
+                    get_upcoming_allowed_moment(after, &at, &timezone, &calendars, &excluded)?;
+
+                last = Some(next);
+
+                let next = in_timezone(next + jitter, &timezone);
+
+                table.add_row(row!(
+                    "*".bright_blue(),
+                    next.weekday().to_string().bright_yellow(),
+                    next.to_string().bright_magenta(),
+                ));
+            }
+
+            println!("{table}");
+        }
+
         Action::Run(RunArgs {
             name,
             use_log_files,
+            force,
         }) => {
             let task = tasks
                 .get(&name)
                 .with_context(|| format!("Task '{}' does not exist.", name.bright_yellow()))?;
 
-            runner(task, &paths, use_log_files)?;
+            // Make the daemon aware of this run, so scheduled runs don't overlap with it
+            let tracker = if is_daemon_running(&paths.daemon_socket_file)? {
+                Some(DaemonRunTracker::connect(&paths.daemon_socket_file)?)
+            } else {
+                None
+            };
+
+            let run_id = match &tracker {
+                Some(tracker) => {
+                    if !force && !handle_overlap(tracker, task) {
+                        bail!(
+                            "Task '{}' is already running (overlap policy: {}), use --force to run it anyway.",
+                            name.bright_yellow(),
+                            task.overlap
+                        );
+                    }
+
+                    match tracker.run_started(task) {
+                        Ok(run_id) => Some(run_id),
+                        Err(err) => {
+                            error_anyhow!(err.context(
+                                "The run is not tracked, so scheduled runs may overlap with it"
+                            ));
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            // Remain in the terminal's process group so the task can be interrupted with Ctrl+C
+            let result = runner(
+                task,
+                &paths,
+                use_log_files,
+                false,
+                RunDetails {
+                    id: run_id.unwrap_or_else(random),
+                    attempt: 1,
+                    trigger: RunTrigger::Manual,
+                    scheduled_at: get_now(),
+                },
+                |pid| {
+                    if let (Some(tracker), Some(run_id)) = (&tracker, run_id) {
+                        tracker.process_spawned(run_id, pid);
+                    }
+                },
+            );
+
+            if let (Some(tracker), Some(run_id)) = (&tracker, run_id) {
+                tracker.run_ended(run_id);
+            }
+
+            result?;
         }
 
         Action::Start(args) => {
@@ -268,11 +860,11 @@ fn inner_main() -> Result<()> {
             info!("List of upcoming / running tasks:");
             info!("");
 
-            let mut table = Table::new("{:>} {:<} {:<} {:<}");
+            let mut table = Table::new("{:>} {:<} {:<} {:<} {:<}");
 
             let now = get_now();
 
-            for RunningTask { task, started } in scheduled.running {
+            for RunningTask { task, started, .. } in scheduled.running {
                 table.add_row(row!(
                     task.name.bright_cyan(),
                     "Running".bright_green(),
@@ -282,10 +874,47 @@ fn inner_main() -> Result<()> {
                     )
                     .bright_blue(),
                     started.to_string().bright_magenta(),
+                    task.describe().bright_black(),
+                ));
+            }
+
+            for WaitingTask {
+                task,
+                since,
+                blocked_by,
+            } in scheduled.waiting
+            {
+                table.add_row(row!(
+                    task.name.bright_cyan(),
+                    "Waiting".bright_red(),
+                    format!(
+                        "for {}",
+                        now.replace_nanosecond(0).unwrap() - since.replace_nanosecond(0).unwrap()
+                    )
+                    .bright_blue(),
+                    format!("for {}", blocked_by.join(", ")).bright_magenta(),
+                    task.describe().bright_black(),
                 ));
             }
 
             for (task, time) in scheduled.upcoming {
+                if task.is_paused_at(time) {
+                    table.add_row(row!(
+                        task.name.bright_cyan(),
+                        "Paused".bright_black(),
+                        match task.paused_until {
+                            Some(until) =>
+                                format!("until {}", until.replace_nanosecond(0).unwrap()),
+                            None => "until resumed".to_owned(),
+                        }
+                        .bright_blue(),
+                        time.to_string().bright_black(),
+                        task.describe().bright_black(),
+                    ));
+
+                    continue;
+                }
+
                 table.add_row(row!(
                     task.name.bright_cyan(),
                     "Scheduled".bright_yellow(),
@@ -295,6 +924,7 @@ fn inner_main() -> Result<()> {
                     )
                     .bright_blue(),
                     time.to_string().bright_magenta(),
+                    task.describe().bright_black(),
                 ));
             }
 
@@ -439,7 +1069,7 @@ fn inner_main() -> Result<()> {
                 None => &entries,
             };
 
-            let mut table = Table::new("{:>} {:>} {:<} {:<} {:<}");
+            let mut table = Table::new("{:>} {:>} {:<} {:<} {:<} {:<}");
 
             for entry in last_entries.iter().rev() {
                 let exists = tasks.values().any(|task| task.id == entry.task_id);
@@ -449,7 +1079,11 @@ fn inner_main() -> Result<()> {
                     false => format!("{} (deleted)", entry.task_name).bright_red(),
                 };
 
-                let result = format!("{}", entry.result);
+                let result = if entry.attempt > 1 {
+                    format!("{} (attempt {})", entry.result, entry.attempt)
+                } else {
+                    format!("{}", entry.result)
+                };
                 let result = match entry.succeeded() {
                     true => result.bright_green(),
                     false => result.bright_red(),
@@ -468,7 +1102,8 @@ fn inner_main() -> Result<()> {
                         - entry.started_at.replace_nanosecond(0).unwrap())
                     .to_string()
                     .bright_magenta(),
-                    result
+                    result,
+                    entry.trigger.to_string().bright_black()
                 ));
             }
 
@@ -478,3 +1113,64 @@ fn inner_main() -> Result<()> {
 
     Ok(())
 }
+
+/// Ensure the daemon is allowed to run tasks as the provided user and group, which requires
+/// it to run as root unless they are its own
+///
+/// When the daemon isn't running, it is assumed it will be started by the current user.
+fn ensure_can_run_as(paths: &Paths, user: Option<&str>, group: Option<&str>) -> Result<()> {
+    if user.is_none() && group.is_none() {
+        return Ok(());
+    }
+
+    let user = user.map(find_user).transpose()?;
+    let group = group.map(find_group).transpose()?;
+
+    let socket_file = &paths.daemon_socket_file;
+
+    let daemon_uid = if is_daemon_running(socket_file)? {
+        Uid::from_raw(DaemonClient::connect(socket_file)?.user_id()?)
+    } else {
+        geteuid()
+    };
+
+    let gid = group
+        .as_ref()
+        .map(|group| group.gid)
+        .or(user.as_ref().map(|user| user.gid));
+
+    ensure_can_switch_to(daemon_uid, user.map(|user| user.uid), gid)
+}
+
+/// Forget about the pauses of a task that ended before its last run, or all of them if it never ran
+/// (in which case there is no run to catch up)
+fn forget_past_pauses(paths: &Paths, task: &mut Task) -> Result<()> {
+    let history = read_history_file(&paths.history_file)?.unwrap_or_else(History::empty);
+
+    let last_run = history
+        .for_task(task.id)
+        .last()
+        .map_or_else(get_now, |entry| entry.started_at);
+
+    task.forget_pauses_before(last_run);
+
+    Ok(())
+}
+
+/// Make the daemon take the changes made to the tasks into account, if it is running
+fn reload_daemon_tasks(paths: &Paths) -> Result<()> {
+    let socket_file = &paths.daemon_socket_file;
+
+    if is_daemon_running(socket_file)? {
+        debug!("Asking the daemon to reload the tasks...");
+
+        let mut client = DaemonClient::connect(socket_file)?;
+        client.reload_tasks()?;
+
+        success!("Daemon successfully reloaded the tasks!");
+    } else {
+        warn!("Warning: the daemon is not running.")
+    }
+
+    Ok(())
+}
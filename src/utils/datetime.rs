@@ -1,22 +1,39 @@
+use std::{
+    fs,
+    ops::Add,
+    path::{Component, Path},
+};
+
+use anyhow::{bail, Context, Result};
 use once_cell::sync::Lazy;
-use time::{OffsetDateTime, UtcOffset};
+use time::{
+    format_description::{well_known::Rfc3339, FormatItem},
+    macros::format_description,
+    Duration, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset,
+};
+use tz::TimeZone;
 
 use crate::warn;
 
-// Required as the offset can fail to be get in some contexts
-static OFFSET: Lazy<UtcOffset> = Lazy::new(|| {
-    UtcOffset::local_offset_at(OffsetDateTime::now_utc()).unwrap_or_else(|_| {
-        warn!("Failed to determine local offset, UTC will be used instead");
-        UtcOffset::UTC
+/// Directory the time zones database is loaded from
+static ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+// Required as the local time zone can fail to be get in some contexts
+static LOCAL_TIMEZONE: Lazy<TimeZone> = Lazy::new(|| {
+    let timezone = match std::env::var("TZ") {
+        Ok(tz) if !tz.is_empty() => TimeZone::from_posix_tz(&tz),
+        _ => TimeZone::local(),
+    };
+
+    timezone.unwrap_or_else(|_| {
+        warn!("Failed to determine local time zone, UTC will be used instead");
+        TimeZone::utc()
     })
 });
 
 pub fn get_now() -> OffsetDateTime {
-    // OffsetDateTime::now_local()
-    //     .context("Failed to determine current date/time")
-    //     .unwrap()
-
-    OffsetDateTime::now_utc().to_offset(*OFFSET)
+    // NOTE: The offset is computed again on each call so DST changes are taken into account
+    in_timezone(OffsetDateTime::now_utc(), &LOCAL_TIMEZONE)
 }
 
 pub fn get_now_second_precision() -> OffsetDateTime {
@@ -26,3 +43,97 @@ pub fn get_now_second_precision() -> OffsetDateTime {
 pub fn second_precision(moment: OffsetDateTime) -> OffsetDateTime {
     moment.replace_nanosecond(0).unwrap()
 }
+
+pub fn local_timezone() -> TimeZone {
+    LOCAL_TIMEZONE.clone()
+}
+
+/// Load an IANA time zone (e.g. `Europe/Paris`) from the system's database
+pub fn load_timezone(name: &str) -> Result<TimeZone> {
+    let path = Path::new(name);
+
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("Invalid time zone name: {name}");
+    }
+
+    let path = Path::new(ZONEINFO_DIR).join(path);
+
+    let data = fs::read(&path)
+        .with_context(|| format!("Failed to read time zone file at path: {}", path.display()))?;
+
+    TimeZone::from_tz_data(&data).with_context(|| format!("Failed to parse time zone: {name}"))
+}
+
+/// Formats accepted for date and times without an explicit offset
+static WALL_CLOCK_FORMATS: &[&[FormatItem<'static>]] = &[
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+    format_description!("[year]-[month]-[day] [hour]:[minute]"),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]"),
+];
+
+/// Parse a date and time like `2024-03-31 02:30`, `2024-03-31` or `2024-03-31T02:30:00+02:00`
+///
+/// Values without an explicit offset are expressed in the provided time zone
+pub fn parse_datetime(input: &str, timezone: &TimeZone) -> Result<OffsetDateTime> {
+    let input = input.trim();
+
+    if let Ok(moment) = OffsetDateTime::parse(input, &Rfc3339) {
+        return Ok(moment);
+    }
+
+    let wall_clock = WALL_CLOCK_FORMATS
+        .iter()
+        .find_map(|format| PrimitiveDateTime::parse(input, format).ok())
+        .or_else(|| {
+            time::Date::parse(input, format_description!("[year]-[month]-[day]"))
+                .ok()
+                .map(|date| date.with_time(Time::MIDNIGHT))
+        })
+        .with_context(|| {
+            format!("Invalid date (expected a format like '2024-03-31 02:30:00'): {input}")
+        })?;
+
+    Ok(from_wall_clock(wall_clock.assume_utc(), timezone))
+}
+
+/// Get the UTC offset of a time zone at a specific moment
+pub fn offset_at(timezone: &TimeZone, moment: OffsetDateTime) -> UtcOffset {
+    timezone
+        .find_local_time_type(moment.unix_timestamp())
+        .ok()
+        .and_then(|local| UtcOffset::from_whole_seconds(local.ut_offset()).ok())
+        .unwrap_or(UtcOffset::UTC)
+}
+
+/// Express a moment in the provided time zone
+pub fn in_timezone(moment: OffsetDateTime, timezone: &TimeZone) -> OffsetDateTime {
+    moment.to_offset(offset_at(timezone, moment))
+}
+
+/// Express a moment as a wall clock time in the provided time zone, using UTC as a neutral offset
+pub fn to_wall_clock(moment: OffsetDateTime, timezone: &TimeZone) -> OffsetDateTime {
+    in_timezone(moment, timezone).replace_offset(UtcOffset::UTC)
+}
+
+/// Find the moment a wall clock time (expressed with an UTC offset) corresponds to in the provided time zone
+pub fn from_wall_clock(wall_clock: OffsetDateTime, timezone: &TimeZone) -> OffsetDateTime {
+    // Offsets in use around the wall clock time, transitions never being closer than a day
+    let before = offset_at(timezone, wall_clock.add(Duration::days(-1)));
+    let after = offset_at(timezone, wall_clock.add(Duration::days(1)));
+
+    let candidates = [before, after]
+        .into_iter()
+        .map(|offset| wall_clock.replace_offset(offset))
+        .filter(|moment| offset_at(timezone, *moment) == moment.offset())
+        .min();
+
+    // When no candidate exists, the wall clock time was skipped by a transition
+    // so we shift it using the offset in use before that transition
+    let moment = candidates.unwrap_or_else(|| wall_clock.replace_offset(before));
+
+    in_timezone(moment, timezone)
+}
@@ -0,0 +1,159 @@
+use anyhow::{bail, Context, Result};
+
+static UNITS: &[(char, u64)] = &[('d', 86_400), ('h', 3_600), ('m', 60), ('s', 1)];
+
+/// Longest duration accepted, so moments can always be computed from it (100 years)
+const MAX_DURATION: u64 = 100 * 365 * 86_400;
+
+/// Parse a duration like `90m`, `1h30m` or `45s` into a number of seconds
+/// (a number without unit is considered to be in seconds)
+pub fn parse_duration(input: &str) -> Result<u64> {
+    if input.is_empty() {
+        bail!("Duration cannot be empty");
+    }
+
+    let total = match input.parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => parse_units(input)?,
+    };
+
+    if total > MAX_DURATION {
+        bail!("Duration is too large (maximum is 100 years): {input}");
+    }
+
+    Ok(total)
+}
+
+/// Same as [`parse_duration`], for durations that cannot be zero
+pub fn parse_non_zero_duration(input: &str) -> Result<u64> {
+    let duration = parse_duration(input)?;
+
+    if duration == 0 {
+        bail!("Duration cannot be zero");
+    }
+
+    Ok(duration)
+}
+
+fn parse_units(input: &str) -> Result<u64> {
+    let mut total = 0u64;
+    let mut number = String::new();
+
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let (_, factor) = UNITS
+            .iter()
+            .find(|(unit, _)| *unit == c)
+            .with_context(|| format!("Invalid unit '{c}' in duration: {input}"))?;
+
+        if number.is_empty() {
+            bail!("Missing number before unit '{c}' in duration: {input}");
+        }
+
+        let value = number
+            .parse::<u64>()
+            .with_context(|| format!("Invalid number in duration: {input}"))?;
+
+        total = value
+            .checked_mul(*factor)
+            .and_then(|value| total.checked_add(value))
+            .with_context(|| format!("Duration is too large: {input}"))?;
+
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        bail!("Missing unit after '{number}' in duration: {input}");
+    }
+
+    Ok(total)
+}
+
+/// Format a number of seconds into a duration like `1h30m`
+pub fn format_duration(secs: u64) -> String {
+    if secs == 0 {
+        return "0s".to_owned();
+    }
+
+    let mut out = String::new();
+    let mut remaining = secs;
+
+    for (unit, factor) in UNITS {
+        if remaining >= *factor {
+            out.push_str(&format!("{}{}", remaining / factor, unit));
+            remaining %= factor;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_duration, parse_duration, parse_non_zero_duration};
+
+    #[test]
+    fn durations_are_parsed() {
+        for (input, secs) in [
+            ("45", 45),
+            ("45s", 45),
+            ("90m", 5_400),
+            ("1h30m", 5_400),
+            ("2d12h", 216_000),
+            ("1m1s", 61),
+            ("0", 0),
+            ("0s", 0),
+        ] {
+            assert_eq!(
+                parse_duration(input).unwrap(),
+                secs,
+                "Invalid duration for '{input}'"
+            );
+        }
+
+        for input in [
+            "",
+            "h",
+            "1x",
+            "1h30",
+            "-1",
+            "1.5h",
+            "36500000d",
+            "99999999999999999999",
+        ] {
+            assert!(
+                parse_duration(input).is_err(),
+                "Accepted invalid duration '{input}'"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_durations_are_rejected() {
+        for input in ["0", "0s", "0h0m"] {
+            assert!(
+                parse_non_zero_duration(input).is_err(),
+                "Accepted zero duration '{input}'"
+            );
+        }
+
+        assert_eq!(parse_non_zero_duration("1s").unwrap(), 1);
+    }
+
+    #[test]
+    fn durations_are_formatted() {
+        for (secs, output) in [
+            (0, "0s"),
+            (45, "45s"),
+            (5_400, "1h30m"),
+            (90_061, "1d1h1m1s"),
+        ] {
+            assert_eq!(format_duration(secs), output);
+            assert_eq!(parse_duration(output).unwrap(), secs);
+        }
+    }
+}
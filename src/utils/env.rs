@@ -0,0 +1,137 @@
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use pomsky_macro::pomsky;
+use regex::Regex;
+
+static VAR_NAME_VALIDATOR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(pomsky!(Start ['a'-'z' 'A'-'Z' '_'] ['a'-'z' 'A'-'Z' '0'-'9' '_']* End)).unwrap()
+});
+
+/// Parse an environment variable assignment like `KEY=VALUE`
+pub fn parse_env_var(input: &str) -> Result<(String, String)> {
+    let (name, value) = input
+        .split_once('=')
+        .with_context(|| format!("Expected a variable like 'KEY=VALUE', found: {input}"))?;
+
+    if !VAR_NAME_VALIDATOR.is_match(name) {
+        bail!("Invalid environment variable name: {name}");
+    }
+
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+/// Parse the content of an env file, with one `KEY=VALUE` assignment per line
+///
+/// Empty lines, comments starting with `#` and `export` prefixes are ignored. Values can be
+/// surrounded by single quotes (kept as is) or double quotes (supporting `\n`, `\"` and `\\`).
+pub fn parse_env_file(content: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = vec![];
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+
+        let (name, value) =
+            parse_env_var(line).with_context(|| format!("Invalid line {} in env file", i + 1))?;
+
+        let value = parse_value(value.trim())
+            .with_context(|| format!("Invalid value at line {} in env file", i + 1))?;
+
+        vars.push((name, value));
+    }
+
+    Ok(vars)
+}
+
+fn parse_value(value: &str) -> Result<String> {
+    if let Some(quoted) = value.strip_prefix('\'') {
+        let Some((value, _)) = quoted.split_once('\'') else {
+            bail!("Unclosed single quote");
+        };
+
+        return Ok(value.to_owned());
+    }
+
+    let Some(quoted) = value.strip_prefix('"') else {
+        // Unquoted values end where a comment starts
+        let value = value.split_once(" #").map_or(value, |(value, _)| value);
+        return Ok(value.trim_end().to_owned());
+    };
+
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+
+    loop {
+        match chars.next() {
+            None => bail!("Unclosed double quote"),
+            Some('"') => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(c @ ('"' | '\\')) => out.push(c),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => bail!("Unclosed double quote"),
+            },
+            Some(c) => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_env_file, parse_env_var};
+
+    #[test]
+    fn env_files_are_parsed() {
+        let content = r#"
+            # Comment
+            PLAIN=value # comment
+            export   EXPORTED=exported
+            EMPTY=
+            SINGLE='single # \n quoted'
+            DOUBLE="double \"quoted\"\nvalue \\ \t"
+            EQUALS=a=b
+        "#;
+
+        let vars = parse_env_file(content).unwrap();
+
+        let expected = [
+            ("PLAIN", "value"),
+            ("EXPORTED", "exported"),
+            ("EMPTY", ""),
+            ("SINGLE", "single # \\n quoted"),
+            ("DOUBLE", "double \"quoted\"\nvalue \\ \\t"),
+            ("EQUALS", "a=b"),
+        ]
+        .map(|(name, value)| (name.to_owned(), value.to_owned()));
+
+        assert_eq!(vars, expected);
+    }
+
+    #[test]
+    fn invalid_env_files_are_rejected() {
+        for content in [
+            "NO_VALUE",
+            "1NAME=value",
+            "SPACED NAME=value",
+            "SPACED = value",
+            "SINGLE='unclosed",
+            "DOUBLE=\"unclosed",
+            "DOUBLE=\"unclosed\\\"",
+        ] {
+            assert!(parse_env_file(content).is_err(), "{content}");
+        }
+
+        let err = parse_env_file("A=1\n\nB\n").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid line 3 in env file");
+
+        assert!(parse_env_var("=value").is_err());
+    }
+}
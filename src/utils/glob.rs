@@ -0,0 +1,88 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+/// Compile a glob pattern matching file names (e.g. `*.csv` or `report-[0-9]?.txt`)
+///
+/// Supported syntax is `*` for any number of characters, `?` for a single character,
+/// and `[...]` (or `[!...]`) for a character in (or not in) a set.
+pub fn compile_glob(pattern: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut set = String::new();
+
+                loop {
+                    match chars.next() {
+                        None => bail!("Unclosed character set in glob pattern: {pattern}"),
+                        Some(']') if !set.is_empty() => break,
+                        Some(c) => set.push(c),
+                    }
+                }
+
+                regex.push('[');
+
+                let set = match set.strip_prefix('!') {
+                    Some(negated) => {
+                        regex.push('^');
+                        negated
+                    }
+                    None => &set,
+                };
+
+                for c in set.chars() {
+                    // Keep ranges, but escape everything else
+                    if c == '-' {
+                        regex.push('-');
+                    } else {
+                        regex.push_str(&regex::escape(&c.to_string()));
+                    }
+                }
+
+                regex.push(']');
+            }
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+
+    Regex::new(&regex).with_context(|| format!("Invalid glob pattern: {pattern}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile_glob;
+
+    #[test]
+    fn globs_are_compiled() {
+        let matches = |pattern, name| compile_glob(pattern).unwrap().is_match(name);
+
+        assert!(matches("*.csv", "report.csv"));
+        assert!(matches("*.csv", ".csv"));
+        assert!(!matches("*.csv", "report.csv.tmp"));
+        assert!(!matches("*.csv", "report.CSV"));
+
+        assert!(matches("report-?.txt", "report-1.txt"));
+        assert!(!matches("report-?.txt", "report-12.txt"));
+
+        assert!(matches("report-[0-9].txt", "report-5.txt"));
+        assert!(!matches("report-[0-9].txt", "report-a.txt"));
+        assert!(matches("report-[!0-9].txt", "report-a.txt"));
+        assert!(!matches("report-[!0-9].txt", "report-5.txt"));
+        assert!(matches("[]a]", "]"));
+
+        // Regex syntax is escaped
+        assert!(matches("a.b(c)+", "a.b(c)+"));
+        assert!(!matches("a.b", "axb"));
+        assert!(matches("[.^]", "^"));
+        assert!(!matches("[.^]", "a"));
+
+        assert!(compile_glob("report-[0-9.txt").is_err());
+        assert!(compile_glob("[9-0]").is_err());
+    }
+}
@@ -1,5 +1,11 @@
 pub mod datetime;
+pub mod duration;
+pub mod env;
+pub mod glob;
 pub mod logging;
 pub mod paging;
+pub mod process;
 pub mod save;
+pub mod size;
 pub mod sleep;
+pub mod users;
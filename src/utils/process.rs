@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nix::{
+    errno::Errno,
+    sys::signal::{kill, killpg, Signal},
+    unistd::Pid,
+};
+
+/// Delay given to processes to exit after being asked to, before they are killed
+pub const TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Check if a process is still running
+pub fn is_process_alive(pid: u32) -> bool {
+    match i32::try_from(pid) {
+        // Sending no signal only checks if the process exists
+        Ok(pid) => !matches!(kill(Pid::from_raw(pid), None), Err(Errno::ESRCH)),
+        Err(_) => false,
+    }
+}
+
+/// Send a signal to a process (does nothing if it already exited)
+pub fn send_signal(pid: u32, signal: Signal) -> Result<()> {
+    let raw = i32::try_from(pid).context("Invalid process ID")?;
+
+    match kill(Pid::from_raw(raw), signal) {
+        Ok(()) | Err(Errno::ESRCH) => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("Failed to send {signal} to process {pid}")),
+    }
+}
+
+/// Send a signal to the process group led by a process, or only to the process itself
+/// if it doesn't lead any group
+pub fn signal_process_group(pid: u32, signal: Signal) -> Result<()> {
+    let raw = i32::try_from(pid).context("Invalid process ID")?;
+
+    match killpg(Pid::from_raw(raw), signal) {
+        Ok(()) => Ok(()),
+        Err(Errno::ESRCH) => send_signal(pid, signal),
+        Err(err) => {
+            Err(err).with_context(|| format!("Failed to send {signal} to process group {pid}"))
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::{
-    fs::{self},
+    fs::{self, File, OpenOptions},
     path::{Path, PathBuf},
+    sync::Mutex,
 };
 
 use anyhow::{Context, Result};
 
 use crate::{
+    calendar::Calendars,
     history::{History, HistoryEntry},
     paths::Paths,
     task::Tasks,
@@ -50,6 +52,22 @@ pub fn read_tasks_no_default(paths: &Paths) -> Result<Tasks> {
     serde_json::from_str(&raw).context("Failed to parse the tasks file")
 }
 
+/// Lock the tasks file until the returned file is dropped, waiting for it to be available
+///
+/// The lock is held on disk, so it is shared by the daemon and the commands modifying the tasks.
+pub fn lock_tasks(paths: &Paths) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&paths.tasks_lock_file)
+        .context("Failed to open the tasks lock file")?;
+
+    file.lock().context("Failed to lock the tasks file")?;
+
+    Ok(file)
+}
+
 pub fn write_tasks(paths: &Paths, tasks: &Tasks) -> Result<()> {
     let raw =
         serde_json::to_string_pretty(tasks).context("Failed to stringify the provided tasks")?;
@@ -57,6 +75,24 @@ pub fn write_tasks(paths: &Paths, tasks: &Tasks) -> Result<()> {
     fs::write(&paths.tasks_file, raw).context("Failed to write the tasks file")
 }
 
+pub fn read_calendars(paths: &Paths) -> Result<Calendars> {
+    if !paths.calendars_file.is_file() {
+        return Ok(Calendars::default());
+    }
+
+    let raw =
+        fs::read_to_string(&paths.calendars_file).context("Failed to read the calendars file")?;
+
+    serde_json::from_str(&raw).context("Failed to parse the calendars file")
+}
+
+pub fn write_calendars(paths: &Paths, calendars: &Calendars) -> Result<()> {
+    let raw = serde_json::to_string_pretty(calendars)
+        .context("Failed to stringify the provided calendars")?;
+
+    fs::write(&paths.calendars_file, raw).context("Failed to write the calendars file")
+}
+
 pub fn read_history_file(history_file: &Path) -> Result<Option<History>> {
     if !history_file.is_file() {
         return Ok(None);
@@ -69,7 +105,12 @@ pub fn read_history_file(history_file: &Path) -> Result<Option<History>> {
     Ok(Some(history))
 }
 
+/// Prevents runs ending at the same time from overwriting each other's history entries
+static HISTORY_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
 pub fn append_to_history(history_file: &Path, entry: HistoryEntry) -> Result<()> {
+    let _guard = HISTORY_WRITE_LOCK.lock().unwrap();
+
     let mut history = if history_file.exists() {
         let content = fs::read_to_string(history_file).context("Failed to read history file")?;
 
@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+
+static UNITS: &[(char, u64)] = &[
+    ('T', 1 << 40),
+    ('G', 1 << 30),
+    ('M', 1 << 20),
+    ('K', 1 << 10),
+];
+
+/// Parse a size like `512M` or `2G` into a number of bytes
+/// (a number without unit is considered to be in bytes)
+pub fn parse_size(input: &str) -> Result<u64> {
+    let size = input.strip_suffix('B').unwrap_or(input);
+
+    let (number, factor) = match UNITS
+        .iter()
+        .find(|(unit, _)| size.ends_with(*unit) || size.ends_with(unit.to_ascii_lowercase()))
+    {
+        Some((_, factor)) => (&size[..size.len() - 1], *factor),
+        None => (size, 1),
+    };
+
+    let size = number
+        .parse::<u64>()
+        .with_context(|| format!("Invalid size (expected a size like '512M' or '2G'): {input}"))?
+        .checked_mul(factor)
+        .with_context(|| format!("Size is too large: {input}"))?;
+
+    if size == 0 {
+        bail!("Size cannot be zero");
+    }
+
+    Ok(size)
+}
+
+/// Format a number of bytes into a size like `512M`, using the largest unit it is a multiple of
+pub fn format_size(bytes: u64) -> String {
+    match UNITS
+        .iter()
+        .find(|(_, factor)| bytes > 0 && bytes.is_multiple_of(*factor))
+    {
+        Some((unit, factor)) => format!("{}{unit}", bytes / factor),
+        None => format!("{bytes}B"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_size, parse_size};
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("512B").unwrap(), 512);
+        assert_eq!(parse_size("4K").unwrap(), 4_096);
+        assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("2gB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("1T").unwrap(), 1 << 40);
+
+        for invalid in ["", "0", "0M", "M", "-1K", "1.5G", "1P", "99999999T"] {
+            assert!(parse_size(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn sizes_are_formatted() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1_000), "1000B");
+        assert_eq!(format_size(4_096), "4K");
+        assert_eq!(format_size(1_536 << 20), "1536M");
+        assert_eq!(format_size(2 << 40), "2T");
+    }
+}
@@ -0,0 +1,111 @@
+use std::{fs, os::unix::fs::MetadataExt, path::Path};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use nix::{
+    libc,
+    unistd::{Gid, Group, Uid, User},
+};
+
+/// Find a user from its name in the system's database
+pub fn find_user(name: &str) -> Result<User> {
+    User::from_name(name)
+        .with_context(|| format!("Failed to look for user '{name}'"))?
+        .with_context(|| format!("User '{name}' does not exist"))
+}
+
+/// Find a group from its name in the system's database
+pub fn find_group(name: &str) -> Result<Group> {
+    Group::from_name(name)
+        .with_context(|| format!("Failed to look for group '{name}'"))?
+        .with_context(|| format!("Group '{name}' does not exist"))
+}
+
+/// Ensure a daemon running as the provided user can run tasks as the provided user and group
+///
+/// Only root can switch to another user or group, so other users can only run tasks as
+/// themselves and their primary group.
+pub fn ensure_can_switch_to(daemon_uid: Uid, uid: Option<Uid>, gid: Option<Gid>) -> Result<()> {
+    if daemon_uid.is_root() {
+        return Ok(());
+    }
+
+    let daemon_user = User::from_uid(daemon_uid)
+        .context("Failed to look for the daemon's user")?
+        .context("The daemon's user does not exist")?;
+
+    if uid.is_some_and(|uid| uid != daemon_user.uid)
+        || gid.is_some_and(|gid| gid != daemon_user.gid)
+    {
+        bail!(
+            "Running tasks as another user or group requires the daemon to run as root (it runs as '{}').",
+            daemon_user.name.bright_yellow()
+        );
+    }
+
+    Ok(())
+}
+
+/// Ensure a user can enter a task's working directory and read its env file by themselves,
+/// when running with the provided group
+///
+/// Only the permission bits are checked, not the access control lists.
+pub fn ensure_user_can_access(
+    uid: Uid,
+    gid: Gid,
+    cwd: Option<&Path>,
+    env_file: Option<&Path>,
+) -> Result<()> {
+    if let Some(cwd) = cwd {
+        if !can_access(uid, gid, cwd, libc::X_OK)? {
+            bail!(
+                "The task's user is not allowed to enter its working directory: {}",
+                cwd.display()
+            );
+        }
+    }
+
+    if let Some(env_file) = env_file {
+        if !can_access(uid, gid, env_file, libc::R_OK)? {
+            bail!(
+                "The task's user is not allowed to read its env file: {}",
+                env_file.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Check if a user can access a path with the provided mode (`R_OK`, `W_OK` or `X_OK`),
+/// which requires to be able to enter all of its parent directories
+fn can_access(uid: Uid, gid: Gid, path: &Path, mode: libc::c_int) -> Result<bool> {
+    if uid.is_root() {
+        return Ok(true);
+    }
+
+    let allows = |path: &Path, mode: libc::c_int| -> Result<bool> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to get the metadata of: {}", path.display()))?;
+
+        let bits = if metadata.uid() == uid.as_raw() {
+            metadata.mode() >> 6
+        } else if gid.as_raw() == metadata.gid() {
+            metadata.mode() >> 3
+        } else {
+            metadata.mode()
+        };
+
+        let mode = u32::try_from(mode).unwrap();
+
+        Ok(bits & mode == mode)
+    };
+
+    for dir in path.ancestors().skip(1) {
+        if !dir.as_os_str().is_empty() && !allows(dir, libc::X_OK)? {
+            return Ok(false);
+        }
+    }
+
+    allows(path, mode)
+}